@@ -1,10 +1,5 @@
-use std::io;
-use std::collections::HashMap;
-use std::collections::LinkedList;
-use std::fmt;
-use std::time::Instant;
-extern crate rand;
-use rand::Rng;
+// Generated by `cargo run -p bundler` from gitc/ and bot/, do not edit.
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::new_without_default, clippy::collapsible_if)]
 
 macro_rules! print_err {
     ($($arg:tt)*) => (
@@ -15,23 +10,610 @@ macro_rules! print_err {
     )
 }
 
+// Structured decision trace, see `Tracer`: trace!(Category::Defend, Level::Info, "event", "key" => value, ...)
+macro_rules! trace {
+    ($category:expr, $level:expr, $event:expr $(, $key:expr => $value:expr)*) => (
+        if $crate::trace::trace_enabled($category, $level) {
+            use $crate::trace::TraceValue;
+            let fields: Vec<(&str, String)> = vec![$(($key, ($value).to_json())),*];
+            $crate::trace::trace_write($category, $level, $event, &fields);
+        }
+    )
+}
+
 macro_rules! parse_input {
     ($x:expr, $t:ident) => ($x.trim().parse::<$t>().unwrap())
 }
 
+pub mod trace {
+use std::env;
+use std::fs;
+use std::cell::RefCell;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Off,
+    Info,
+    Debug
+}
+
+#[derive(Clone, Copy)]
+pub enum Category {
+    Map,
+    Turn,
+    Defend,
+    Attack,
+    Neutral,
+    Inc,
+    Bomb,
+    Search,
+    Timing,
+    Logistics,
+    Opponent,
+    Sim
+}
+
+pub const CATEGORIES: [Category; 12] = [Category::Map, Category::Turn, Category::Defend, Category::Attack, Category::Neutral,
+                                    Category::Inc, Category::Bomb, Category::Search, Category::Timing, Category::Logistics, Category::Opponent,
+                                    Category::Sim];
+
+impl Category {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Category::Map => "map",
+            Category::Turn => "turn",
+            Category::Defend => "defend",
+            Category::Attack => "attack",
+            Category::Neutral => "neutral",
+            Category::Inc => "inc",
+            Category::Bomb => "bomb",
+            Category::Search => "search",
+            Category::Timing => "timing",
+            Category::Logistics => "logistics",
+            Category::Opponent => "opponent",
+            Category::Sim => "sim"
+        }
+    }
+}
+
+// JSON lines trace, silent unless GITC_TRACE sets levels per category ("defend=debug,attack=info" or "all=info")
+// Lines go to stderr, or are appended to GITC_TRACE_FILE
+struct Tracer {
+    levels: [Level; 12],
+    output: Option<fs::File>,
+    turn: i32
+}
+
+impl Tracer {
+    pub fn load() -> Tracer {
+        let mut tracer = Tracer{levels: [Level::Off; 12], output: None, turn: 0};
+        let config = match env::var("GITC_TRACE") {
+            Ok(config) => config,
+            Err(_) => return tracer
+        };
+
+        for entry in config.split(',').map(|entry| entry.trim()).filter(|entry| !entry.is_empty()) {
+            let mut parts = entry.splitn(2, '=');
+            let name = parts.next().unwrap();
+            let level = match parts.next().unwrap_or("info") {
+                "off" => Level::Off,
+                "info" => Level::Info,
+                "debug" => Level::Debug,
+                level => { print_err!("Unknown trace level {}", level); continue }
+            };
+            for (i, category) in CATEGORIES.iter().enumerate() {
+                if name == "all" || name == category.name() {
+                    tracer.levels[i] = level;
+                }
+            }
+        }
+
+        if let Ok(path) = env::var("GITC_TRACE_FILE") {
+            match fs::OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => tracer.output = Some(file),
+                Err(err) => print_err!("Cannot open trace {}: {}", path, err)
+            }
+        }
+
+        return tracer;
+    }
+}
+
+thread_local! {
+    static TRACER: RefCell<Tracer> = RefCell::new(Tracer::load());
+}
+
+pub fn trace_enabled(category: Category, level: Level) -> bool {
+    return TRACER.with(|tracer| level <= tracer.borrow().levels[category as usize]);
+}
+
+pub fn trace_turn(turn: i32) {
+    TRACER.with(|tracer| tracer.borrow_mut().turn = turn);
+}
+
+pub fn trace_write(category: Category, level: Level, event: &str, fields: &[(&str, String)]) {
+    use std::io::Write;
+    TRACER.with(|tracer| {
+        let mut tracer = tracer.borrow_mut();
+        let mut line = format!("{{\"turn\":{},\"cat\":\"{}\",\"level\":\"{}\",\"event\":{}",
+                               tracer.turn, category.name(), if level == Level::Debug { "debug" } else { "info" }, event.to_json());
+        for &(key, ref value) in fields.iter() {
+            line.push_str(&format!(",{}:{}", key.to_json(), value));
+        }
+        line.push('}');
+
+        match tracer.output {
+            Some(ref mut file) => { writeln!(file, "{}", line).ok(); },
+            None => print_err!("{}", line)
+        }
+    });
+}
+
+pub trait TraceValue {
+    fn to_json(&self) -> String;
+}
+
+impl TraceValue for i32 {
+    fn to_json(&self) -> String { self.to_string() }
+}
+
+impl TraceValue for u64 {
+    fn to_json(&self) -> String { self.to_string() }
+}
+
+impl TraceValue for usize {
+    fn to_json(&self) -> String { self.to_string() }
+}
+
+impl TraceValue for f32 {
+    fn to_json(&self) -> String { if self.is_finite() { self.to_string() } else { "null".to_string() } }
+}
+
+impl TraceValue for bool {
+    fn to_json(&self) -> String { self.to_string() }
+}
+
+impl TraceValue for str {
+    fn to_json(&self) -> String {
+        let mut json = String::from("\"");
+        for c in self.chars() {
+            match c {
+                '"' => json.push_str("\\\""),
+                '\\' => json.push_str("\\\\"),
+                c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+                c => json.push(c)
+            }
+        }
+        json.push('"');
+        return json;
+    }
+}
+
+impl TraceValue for String {
+    fn to_json(&self) -> String { self.as_str().to_json() }
+}
+
+impl<T: TraceValue> TraceValue for Vec<T> {
+    fn to_json(&self) -> String {
+        let values: Vec<String> = self.iter().map(|value| value.to_json()).collect();
+        return format!("[{}]", values.join(","));
+    }
+}
+}
+pub mod rng {
+use std::env;
+use std::time::Duration;
+
+// xorshift64*, seeded so that random searches and local games can be replayed
+#[derive(Clone)]
+pub struct Rng {
+    pub state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // splitmix64 so that close seeds give unrelated sequences, the state must not be 0
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        return Rng{state: (z ^ (z >> 31)).max(1)};
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return self.state.wrapping_mul(0x2545F4914F6CDD1D);
+    }
+
+    // In [low, high)
+    pub fn gen_range(&mut self, low: i32, high: i32) -> i32 {
+        return low + ((self.next_u64() >> 32) % (high - low) as u64) as i32;
+    }
+
+    pub fn gen_bool(&mut self) -> bool {
+        return self.next_u64() >> 63 == 1;
+    }
+
+    // Independent generator for a cloned state
+    pub fn fork(&mut self) -> Rng {
+        return Rng::new(self.next_u64());
+    }
+}
+
+// --seed N, then GITC_SEED, else the clock. The seed is always logged so that the run can be replayed
+pub fn startup_seed(args: &mut Vec<String>) -> u64 {
+    let mut seed = env::var("GITC_SEED").ok().and_then(|seed| seed.trim().parse::<u64>().ok());
+    if let Some(position) = args.iter().position(|arg| arg == "--seed") {
+        seed = args.get(position + 1).and_then(|seed| seed.parse::<u64>().ok());
+        args.drain(position..(position + 2).min(args.len()));
+    }
+
+    let seed = seed.unwrap_or_else(|| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+        now.as_secs() ^ (now.subsec_nanos() as u64) << 20
+    });
+    print_err!("Seed: {}", seed);
+    return seed;
+}
+}
+pub mod time {
+use std::time::{Duration, Instant};
+
+pub const FIRST_TURN_BUDGET_MS: u64 = 1000;
+pub const SAFETY_MARGIN_MS: u64 = 5;
+
+// Deadline of the current turn, started once the turn input has been read
+#[derive(Clone, Copy)]
+pub struct TimeBudget {
+    pub start: Instant,
+    pub budget: Duration
+}
+
+impl TimeBudget {
+    pub fn start(nb_turn: i32, turn_budget_ms: i32) -> TimeBudget {
+        let budget_ms = if nb_turn == 0 { FIRST_TURN_BUDGET_MS } else { turn_budget_ms as u64 };
+        return TimeBudget{start: Instant::now(), budget: Duration::from_millis(budget_ms)};
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        return self.start.elapsed().as_millis() as u64;
+    }
+
+    pub fn remaining(&self) -> Duration {
+        return self.budget.checked_sub(self.start.elapsed()).unwrap_or(Duration::from_millis(0));
+    }
+
+    // Keep SAFETY_MARGIN_MS to finish the turn and print the commands
+    pub fn should_stop(&self) -> bool {
+        return self.remaining() <= Duration::from_millis(SAFETY_MARGIN_MS);
+    }
+}
+}
+pub mod eval {
+use std::env;
+use std::fs;
+
+use crate::model::{GameState, HasOwner};
+use crate::params::parse_config;
+
+pub const TERMINAL_SCORE: f32 = 1_000_000.0; // Above any evaluation of a running game
+
+// Weights of the evaluation function, each term is computed as player minus enemy
+#[derive(Clone, Copy)]
+pub struct Evaluator {
+    pub owned_cyborgs: f32,
+    pub troops: f32,
+    pub production: f32,
+    pub projected_captures: f32, // Production that changes side when the troops in flight land, owned production is not counted again
+    pub centrality: f32,
+    pub bombs: f32, // Bombs left, ours minus the enemy's as inferred from its launches
+    pub frontline: f32
+}
+
+impl Evaluator {
+    pub fn new() -> Evaluator {
+        Evaluator {
+            owned_cyborgs: 1.0,
+            troops: 1.0,
+            production: 10.0,
+            projected_captures: 10.0,
+            centrality: 0.0,
+            bombs: 0.0,
+            frontline: 0.0
+        }
+    }
+
+    // GITC_EVAL_FILE is a file with one "name=weight" per line, GITC_EVAL is "name=weight,name=weight" and wins over the file
+    pub fn load() -> Evaluator {
+        return Evaluator::load_from(env::var("GITC_EVAL_FILE").ok(), env::var("GITC_EVAL").ok());
+    }
+
+    pub fn load_from(path: Option<String>, config: Option<String>) -> Evaluator {
+        let mut evaluator = Evaluator::new();
+
+        if let Some(path) = path {
+            match fs::read_to_string(&path) {
+                Ok(config) => evaluator.parse(&config),
+                Err(err) => print_err!("[EVAL] Cannot read {}: {}", path, err)
+            }
+        }
+
+        if let Some(config) = config {
+            evaluator.parse(&config);
+        }
+
+        return evaluator;
+    }
+
+    pub fn parse(&mut self, config: &str) {
+        for (key, value) in parse_config(config) {
+            if !self.set(&key, value) { print_err!("[EVAL] Unknown weight {}", key) }
+        }
+    }
+
+    pub fn entries(&self) -> Vec<(&'static str, f32)> {
+        return vec![
+            ("owned_cyborgs", self.owned_cyborgs),
+            ("troops", self.troops),
+            ("production", self.production),
+            ("projected_captures", self.projected_captures),
+            ("centrality", self.centrality),
+            ("bombs", self.bombs),
+            ("frontline", self.frontline)
+        ];
+    }
+
+    pub fn set(&mut self, key: &str, value: f32) -> bool {
+        match key {
+            "owned_cyborgs" => self.owned_cyborgs = value,
+            "troops" => self.troops = value,
+            "production" => self.production = value,
+            "projected_captures" => self.projected_captures = value,
+            "centrality" => self.centrality = value,
+            "bombs" => self.bombs = value,
+            "frontline" => self.frontline = value,
+            _ => return false
+        }
+        return true;
+    }
+}
+
+impl GameState {
+    pub fn evaluate(&self) -> f32 {
+        if self.is_terminal() {
+            return self.winner() as f32 * TERMINAL_SCORE;
+        }
+
+        let weights = &self.params.eval;
+        let mut owned_cyborgs = 0;
+        let mut production = 0;
+        let mut centrality: f32 = 0.0;
+        let mut frontline: f32 = 0.0;
+
+        // Cyborg in factories
+        for factory in self.factories.values() {
+            if factory.is_neutral() { continue }
+            let side = factory.owner;
+
+            owned_cyborgs += factory.cyborg_count * side;
+            production += factory.production * side;
+
+            // Close to everything is better
+            for &(distance, _) in factory.distances.iter() {
+                centrality += side as f32 / distance as f32;
+            }
+
+            // Cyborg advantage over each enemy factory, the closer the more pressure
+            if factory.is_player() {
+                for &(distance, id2) in factory.distances.iter() {
+                    let enemy_fac = self.factories.get(&id2).unwrap();
+                    if !enemy_fac.is_enemy() { continue }
+                    frontline += (factory.cyborg_count - enemy_fac.cyborg_count) as f32 / distance as f32;
+                }
+            }
+        }
+
+        // Cyborg in troops
+        let mut troops = 0;
+        for troop in self.troops.iter() {
+            troops += troop.cyborg_count * troop.owner;
+        }
+
+        // Factories that change side when the troops in flight land, the neutral garrison fights the arrivals
+        let mut projected_captures = 0;
+        for factory in self.factories.values() {
+            let mut arrivals = 0;
+            for troop in self.troops.iter() {
+                if factory.id == troop.factory_end {
+                    arrivals += troop.cyborg_count * troop.owner;
+                }
+            }
+
+            let projected_owner = if factory.is_neutral() {
+                if arrivals.abs() > factory.cyborg_count { arrivals.signum() } else { 0 }
+            } else {
+                let cyborg_count = factory.cyborg_count * factory.owner + arrivals;
+                if cyborg_count == 0 { factory.owner } else { cyborg_count.signum() }
+            };
+            // Production gained minus production lost, a factory taken from the enemy counts twice
+            projected_captures += (projected_owner - factory.owner) * factory.production;
+        }
+
+        let score = weights.owned_cyborgs * owned_cyborgs as f32
+            + weights.troops * troops as f32
+            + weights.production * production as f32
+            + weights.projected_captures * projected_captures as f32
+            + weights.centrality * centrality
+            + weights.bombs * (self.bomb_count - self.enemy_bomb_count) as f32
+            + weights.frontline * frontline;
+
+        //print_err!("Score : {}", score);
+        return score;
+    }
+}
+
+}
+pub mod params {
+use std::env;
+use std::fs;
+
+use crate::eval::Evaluator;
+use crate::rng::Rng;
+
+// Tunable constants of the rule based AI, see `tune`
+#[derive(Clone, Copy)]
+pub struct Params {
+    pub inc_threshold: i32,
+    pub lookahead: i32, // Max simulated turns when predicting a capture
+    pub bomb_count: i32,
+    pub backline_garrison: i32, // Cyborgs kept by a backline factory, the surplus goes to the frontline
+    pub threat_horizon: i32, // Enemy garrisons within this distance are a threat even before they launch
+    pub max_orders: i32, // Orders per factory and per turn of allocate_moves, 0 turns it off
+    pub feint_value: f32, // Worth of a one cyborg troop to an enemy factory, a cyborg is worth owned_cyborgs
+    pub turn_budget_ms: i32, // Referee limit of a turn, the first turn has FIRST_TURN_BUDGET_MS
+    pub eval: Evaluator
+}
+
+impl Params {
+    pub fn new() -> Params {
+        Params {
+            inc_threshold: 15,
+            lookahead: 20,
+            bomb_count: 2,
+            backline_garrison: 5,
+            threat_horizon: 5,
+            max_orders: 3,
+            feint_value: 0.0,
+            turn_budget_ms: 50,
+            eval: Evaluator::new()
+        }
+    }
+
+    // Same format as the evaluator: GITC_PARAMS_FILE then GITC_PARAMS, evaluator weights are accepted too
+    pub fn load() -> Params {
+        let mut params = Params::new();
+        params.eval = Evaluator::load();
+
+        if let Ok(path) = env::var("GITC_PARAMS_FILE") {
+            match fs::read_to_string(&path) {
+                Ok(config) => params.parse(&config),
+                Err(err) => print_err!("[PARAMS] Cannot read {}: {}", path, err)
+            }
+        }
+
+        if let Ok(config) = env::var("GITC_PARAMS") {
+            params.parse(&config);
+        }
+
+        return params;
+    }
+
+    pub fn parse(&mut self, config: &str) {
+        for (key, value) in parse_config(config) {
+            if !self.set(&key, value) { print_err!("[PARAMS] Unknown param {}", key) }
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: f32) -> bool {
+        match key {
+            "inc_threshold" => self.inc_threshold = (value.round() as i32).max(10),
+            "lookahead" => self.lookahead = (value.round() as i32).max(1),
+            "bomb_count" => self.bomb_count = (value.round() as i32).clamp(0, 2),
+            "backline_garrison" => self.backline_garrison = (value.round() as i32).max(0),
+            "threat_horizon" => self.threat_horizon = (value.round() as i32).max(0),
+            "max_orders" => self.max_orders = (value.round() as i32).max(0),
+            "feint_value" => self.feint_value = value.max(0.0),
+            "turn_budget_ms" => self.turn_budget_ms = (value.round() as i32).max(1),
+            _ => return self.eval.set(key, value)
+        }
+        return true;
+    }
+
+    pub fn entries(&self) -> Vec<(&'static str, f32)> {
+        let mut entries = vec![
+            ("inc_threshold", self.inc_threshold as f32),
+            ("lookahead", self.lookahead as f32),
+            ("bomb_count", self.bomb_count as f32),
+            ("backline_garrison", self.backline_garrison as f32),
+            ("threat_horizon", self.threat_horizon as f32),
+            ("max_orders", self.max_orders as f32),
+            ("feint_value", self.feint_value)
+        ];
+        entries.extend(self.eval.entries());
+        return entries;
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut config = String::new();
+        for (key, value) in self.entries() {
+            config.push_str(&format!("{}={}\n", key, value));
+        }
+        config.push_str(&format!("turn_budget_ms={}\n", self.turn_budget_ms));
+        return config;
+    }
+
+    // Move one random param by 20% (or by 1 if it is zero)
+    pub fn mutate(&self, rng: &mut Rng) -> (Params, &'static str) {
+        let entries = self.entries();
+        let (key, value) = entries[rng.gen_range(0, entries.len() as i32) as usize];
+        let step = if value == 0.0 { 1.0 } else { (value * 0.2).abs().max(1.0) };
+
+        let mut params = *self;
+        params.set(key, if rng.gen_bool() { value + step } else { value - step });
+        return (params, key);
+    }
+}
+
+// "name=value" entries separated by commas or new lines, '#' starts a comment line
+pub fn parse_config(config: &str) -> Vec<(String, f32)> {
+    let mut entries = Vec::new();
+    for entry in config.split([',', '\n']) {
+        let entry = entry.trim();
+        if entry.is_empty() || entry.starts_with('#') { continue }
+
+        let mut parts = entry.splitn(2, '=');
+        let key = parts.next().unwrap().trim();
+        match parts.next().and_then(|value| value.trim().parse::<f32>().ok()) {
+            Some(value) => entries.push((key.to_string(), value)),
+            None => print_err!("Invalid config entry {}", entry)
+        }
+    }
+    return entries;
+}
+}
+pub mod model {
+use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::collections::LinkedList;
+use std::rc::Rc;
+
+use crate::inference::EnemyActions;
+use crate::params::Params;
+use crate::profile::OpponentProfile;
+use crate::rng::Rng;
+use crate::territory::Territory;
+use crate::time::TimeBudget;
+use crate::tracking::TrackedTroop;
+use crate::trace::{Category, Level};
+
+pub const MAX_TURNS: i32 = 200;
+pub const MAX_PRODUCTION: i32 = 3;
+pub const INC_COST: i32 = 10;
+pub const BOMB_DISRUPTION: i32 = 5;
 
 #[derive(Clone)]
-struct Factory {
-    id: i32,
-    owner: i32,
-    cyborg_count: i32,
-    production: i32,
-    distances: Vec<(i32, i32)>, // (distance, id)
-    cyborg_count_combat: i32,
-    cyborg_remaining: i32 // For random strategy
+pub struct Factory {
+    pub id: i32,
+    pub owner: i32,
+    pub cyborg_count: i32,
+    pub production: i32,
+    pub distances: Vec<(i32, i32)>, // (distance, id)
+    pub disrupted: i32, // Turns before the production resumes after a bomb
+    pub cyborg_remaining: i32 // For random strategy
 }
 
-trait HasOwner {
+pub trait HasOwner {
     fn get_owner(&self) -> i32;
 
 
@@ -53,53 +635,95 @@ impl HasOwner for Factory {
 }
 
 #[derive(Clone)]
-struct Troop {
-    id: i32,
-    owner: i32,
-    factory_start: i32,
-    factory_end: i32,
-    cyborg_count: i32,
-    turn_remaining: i32
+pub struct Troop {
+    pub id: i32,
+    pub owner: i32,
+    pub factory_start: i32,
+    pub factory_end: i32,
+    pub cyborg_count: i32,
+    pub turn_remaining: i32
 }
 
 impl HasOwner for Troop {
     fn get_owner(&self) -> i32 { self.owner }
 }
 
+// The target and the remaining turns of enemy bombs are unknown (-1)
+#[derive(Clone)]
+pub struct Bomb {
+    pub id: i32,
+    pub owner: i32,
+    pub factory_start: i32,
+    pub factory_end: i32,
+    pub turn_remaining: i32
+}
+
+impl HasOwner for Bomb {
+    fn get_owner(&self) -> i32 { self.owner }
+}
 
 #[derive(Clone)]
-struct GameState {
-    factory_distance: HashMap<(i32, i32), i32>,
-    factories: HashMap<i32, Factory>,
-    troops: LinkedList<Troop>,
-    commands: Vec<String>,
-    bomb_count: i32,
-    bomb_last: i32,
-    troop_commands: LinkedList<Troop>,
-    start: Instant,
-    nb_turn: i32,
-    bombing: bool
+pub struct GameState {
+    pub factory_distance: HashMap<(i32, i32), i32>,
+    pub factories: BTreeMap<i32, Factory>, // Sorted so that strategies are deterministic
+    pub troops: LinkedList<Troop>,
+    pub bombs: Vec<Bomb>,
+    pub commands: Vec<String>,
+    pub bomb_count: i32,
+    pub bomb_last: i32,
+    pub troop_commands: LinkedList<Troop>,
+    pub rng: Rng,
+    pub nb_turn: i32,
+    pub bombing: bool,
+    pub params: Params,
+    pub budget: TimeBudget,
+    pub territory: Territory, // Refreshed at the start of each turn
+    pub next_entity_id: i32, // Id of the next troop or bomb created by the simulator
+    pub last_seen: Option<Rc<Snapshot>>, // Previous turn, compared with the current one
+    pub profile: OpponentProfile,
+    pub enemy_actions: EnemyActions, // Inferred from the last turn
+    pub enemy_bomb_count: i32,
+    pub tracked_troops: BTreeMap<i32, TrackedTroop> // Troops in flight by entity id
 }
 
+// What the referee showed on a turn
+#[derive(Clone)]
+pub struct Snapshot {
+    pub nb_turn: i32,
+    pub factories: BTreeMap<i32, Factory>,
+    pub troops: LinkedList<Troop>,
+    pub bombs: Vec<Bomb>,
+    pub orders: Vec<String> // Our commands of that turn
+}
 
 impl GameState {
-    fn new() -> GameState {
+    pub fn new() -> GameState {
         GameState {
             factory_distance: HashMap::new(),
-            factories: HashMap::new(),
+            factories: BTreeMap::new(),
             troops: LinkedList::new(),
+            bombs: Vec::new(),
             commands: Vec::new(),
             bomb_count: 2,
             bomb_last: -99,
             troop_commands: LinkedList::new(),
-            start: Instant::now(),
+            rng: Rng::new(0),
             nb_turn: 0,
-            bombing: false
+            bombing: false,
+            params: Params::new(),
+            budget: TimeBudget::start(0, 0),
+            territory: Territory::default(),
+            next_entity_id: 0,
+            last_seen: None,
+            profile: OpponentProfile::default(),
+            enemy_actions: EnemyActions::default(),
+            enemy_bomb_count: 2,
+            tracked_troops: BTreeMap::new()
         }
     }
 
-    fn init_factories_distance(&mut self, factory_count: i32) {
-        for i in 0..factory_count as i32 {
+    pub fn init_factories_distance(&mut self, factory_count: i32) {
+        for i in 0..factory_count {
             let mut distances: Vec<(i32, i32)> = Vec::new();
             for (&(id1, id2), distance) in self.factory_distance.iter() {
                 if id1 == i {
@@ -111,26 +735,107 @@ impl GameState {
             }
 
             distances.sort();
-            for &(id1, id2) in distances.iter() {
-                print_err!("Distance:{} Id:{}", id1, id2);
+            trace!(Category::Map, Level::Debug, "distances", "factory" => i,
+                   "distances" => distances.iter().map(|&(distance, id2)| vec![distance, id2]).collect::<Vec<_>>());
+
+            self.factories.insert(i, Factory{id: i, owner: -99, cyborg_count: -99, production: -99, distances: distances, disrupted: 0, cyborg_remaining: 0});
+        }
+    }
+    pub fn snapshot(&self) -> Snapshot {
+        return Snapshot{nb_turn: self.nb_turn, factories: self.factories.clone(), troops: self.troops.clone(), bombs: self.bombs.clone(), orders: Vec::new()};
+    }
+
+    pub fn new_entity_id(&mut self) -> i32 {
+        self.next_entity_id += 1;
+        return self.next_entity_id - 1;
+    }
+
+    pub fn distance(&self, id1: i32, id2: i32) -> i32 {
+        return *self.factory_distance.get(&(id1, id2))
+            .or_else(|| self.factory_distance.get(&(id2, id1)))
+            .unwrap();
+    }
+}
+}
+pub mod protocol {
+use std::io;
+use std::env;
+use std::fs;
+use std::collections::VecDeque;
+
+use crate::model::{Bomb, GameState, Troop};
+use crate::time::TimeBudget;
+use crate::trace::{Category, Level};
+
+// Referee lines from stdin or from a replay, optionally recorded to the GITC_REPLAY file
+// Replay format: "P <params>" and "S <seed>" once, then "I <input line>", "O <output line>" and "T <elapsed ms>" for each turn
+pub struct Input {
+    replay: Option<VecDeque<String>>,
+    recorder: Option<fs::File>
+}
+
+impl Input {
+    pub fn stdin() -> Input {
+        let recorder = env::var("GITC_REPLAY").ok().and_then(|path| match fs::File::create(&path) {
+            Ok(file) => Some(file),
+            Err(err) => { print_err!("Cannot record replay {}: {}", path, err); None }
+        });
+        return Input{replay: None, recorder: recorder};
+    }
+
+    pub fn replay(lines: VecDeque<String>) -> Input {
+        return Input{replay: Some(lines), recorder: None};
+    }
+
+    pub fn read_line(&mut self) -> String {
+        let line = match self.replay {
+            Some(ref mut lines) => lines.pop_front().expect("Replay has no more input"),
+            None => {
+                let mut input_line = String::new();
+                io::stdin().read_line(&mut input_line).unwrap();
+                input_line.trim_end_matches(['\n', '\r']).to_string()
             }
+        };
+        self.record('I', &line);
+        return line;
+    }
+
+    pub fn record(&mut self, tag: char, line: &str) {
+        use std::io::Write;
+        if let Some(ref mut file) = self.recorder {
+            writeln!(file, "{} {}", tag, line).ok();
+        }
+    }
+}
 
-            print_err!("---------");
+impl GameState {
+    pub fn init_links(&mut self, input: &mut Input) {
+        let input_line = input.read_line();
+        let factory_count = parse_input!(input_line, i32); // the number of factories
+        let input_line = input.read_line();
+        let link_count = parse_input!(input_line, i32); // the number of links between factories
+        for _ in 0..link_count as usize {
+            let input_line = input.read_line();
+            let inputs = input_line.split(" ").collect::<Vec<_>>();
+            let factory_1 = parse_input!(inputs[0], i32);
+            let factory_2 = parse_input!(inputs[1], i32);
+            let distance = parse_input!(inputs[2], i32);
 
-            self.factories.insert(i, Factory{id: i, owner: -99, cyborg_count: -99, production: -99, distances: distances, cyborg_count_combat: 0, cyborg_remaining: 0});
+            trace!(Category::Map, Level::Debug, "link", "factory_1" => factory_1, "factory_2" => factory_2, "distance" => distance);
+            self.factory_distance.insert((factory_1, factory_2), distance);
         }
+        self.init_factories_distance(factory_count);
     }
 
-    fn init_entities(&mut self) {
+    pub fn init_entities(&mut self, input: &mut Input) {
         self.troops.clear();
+        self.bombs.clear();
         self.bombing = false;
 
-        let mut input_line = String::new();
-        io::stdin().read_line(&mut input_line).unwrap();
+        let input_line = input.read_line();
         let entity_count = parse_input!(input_line, i32); // the number of entities (e.g. factories and troops)
-        for i in 0..entity_count as usize {
-            let mut input_line = String::new();
-            io::stdin().read_line(&mut input_line).unwrap();
+        for _ in 0..entity_count as usize {
+            let input_line = input.read_line();
             let inputs = input_line.split(" ").collect::<Vec<_>>();
             let entity_id = parse_input!(inputs[0], i32);
             let entity_type = inputs[1].trim().to_string();
@@ -140,36 +845,1263 @@ impl GameState {
             let arg_4 = parse_input!(inputs[5], i32);
             let arg_5 = parse_input!(inputs[6], i32);
 
-            if entity_type == "FACTORY" {
-                let mut factory = self.factories.get_mut(&entity_id).unwrap();
-                factory.owner = arg_1;
-                factory.cyborg_count = arg_2;
-                factory.production = arg_3;
-                factory.cyborg_remaining = factory.cyborg_count;
-            } else if entity_type == "TROOP" {
-                self.troops.push_back(Troop{id: entity_id, owner: arg_1, factory_start: arg_2, factory_end: arg_3, cyborg_count: arg_4, turn_remaining: arg_5});
-            } else if entity_type == "BOMB" {
-                if arg_1 == -1 { self.bombing = true }
+            if entity_type == "FACTORY" {
+                let factory = self.factories.get_mut(&entity_id).unwrap();
+                factory.owner = arg_1;
+                factory.cyborg_count = arg_2;
+                factory.production = arg_3;
+                factory.disrupted = arg_4;
+                factory.cyborg_remaining = factory.cyborg_count;
+            } else if entity_type == "TROOP" {
+                self.next_entity_id = self.next_entity_id.max(entity_id + 1);
+                self.troops.push_back(Troop{id: entity_id, owner: arg_1, factory_start: arg_2, factory_end: arg_3, cyborg_count: arg_4, turn_remaining: arg_5});
+            } else if entity_type == "BOMB" {
+                if arg_1 == -1 { self.bombing = true }
+                self.next_entity_id = self.next_entity_id.max(entity_id + 1);
+                self.bombs.push(Bomb{id: entity_id, owner: arg_1, factory_start: arg_2, factory_end: arg_3, turn_remaining: arg_4});
+            }
+
+        }
+
+        self.budget = TimeBudget::start(self.nb_turn, self.params.turn_budget_ms);
+    }
+
+    // A command read back from a replay or a previous turn, `side` owns the MOVE
+    pub fn push_order(&mut self, side: i32, order: &str) {
+        let words: Vec<&str> = order.split_whitespace().collect();
+        if words.first() == Some(&"MOVE") && words.len() == 4 {
+            self.troop_commands.push_back(Troop{id: 999, owner: side, factory_start: parse_input!(words[1], i32), factory_end: parse_input!(words[2], i32),
+                                                cyborg_count: parse_input!(words[3], i32), turn_remaining: 0});
+        } else {
+            self.commands.push(order.to_string());
+        }
+    }
+
+    pub fn print_commands(&mut self) {
+        println!("{}", self.take_commands());
+    }
+
+    pub fn take_commands(&mut self) -> String {
+
+        let mut final_command = "MSG El Psy Congroo".to_string();
+        for command in self.commands.iter() {
+            final_command.push(';');
+            final_command.push_str(command);
+        }
+
+        for troop in self.troop_commands.iter() {
+            final_command.push(';');
+            final_command.push_str(&format!("MOVE {} {} {}", troop.factory_start, troop.factory_end, troop.cyborg_count));
+        }
+
+        self.troop_commands.clear();
+        self.commands.clear();
+
+        return final_command;
+    }
+}
+}
+pub mod sim {
+use std::collections::BTreeMap;
+use std::collections::LinkedList;
+
+use crate::model::{Bomb, Factory, GameState, HasOwner, Troop, BOMB_DISRUPTION, INC_COST, MAX_PRODUCTION, MAX_TURNS};
+
+impl Factory {
+    // INC is ignored by the referee without enough cyborgs or at max production
+    pub fn increase_production(&mut self) {
+        if self.cyborg_count < INC_COST || self.production >= MAX_PRODUCTION { return }
+        self.cyborg_count -= INC_COST;
+        self.production += 1;
+    }
+
+    pub fn produce(&mut self) {
+        if self.disrupted > 0 {
+            self.disrupted -= 1;
+        } else if !self.is_neutral() {
+            self.cyborg_count += self.production;
+        }
+    }
+
+    // Half of the garrison with a minimum of 10 cyborgs, then no production for 5 turns
+    pub fn explode_bomb(&mut self) {
+        self.cyborg_count -= self.cyborg_count.min((self.cyborg_count / 2).max(10));
+        self.disrupted = BOMB_DISRUPTION;
+    }
+
+    // Troops arriving the same turn fight each other first, the survivors reinforce or fight the garrison
+    pub fn resolve_arrivals(&mut self, player_count: i32, enemy_count: i32) {
+        let (side, count) = if player_count > enemy_count {
+            (1, player_count - enemy_count)
+        } else {
+            (-1, enemy_count - player_count)
+        };
+        if count == 0 { return }
+
+        if self.owner == side {
+            self.cyborg_count += count;
+        } else if count > self.cyborg_count {
+            self.owner = side;
+            self.cyborg_count = count - self.cyborg_count;
+        } else {
+            // On a tie the factory keeps its owner with an empty garrison
+            self.cyborg_count -= count;
+        }
+    }
+}
+
+impl GameState {
+    pub fn sim_next_turn(&mut self) {
+        // Troops Moving, the arrivals only fight after the production
+        let mut arrivals: BTreeMap<i32, (i32, i32)> = BTreeMap::new(); // factory -> (player cyborgs, enemy cyborgs)
+        for troop in self.troops.iter_mut() {
+            troop.turn_remaining -= 1;
+            if troop.turn_remaining == 0 {
+                let arrival = arrivals.entry(troop.factory_end).or_insert((0, 0));
+                if troop.is_player() {
+                    arrival.0 += troop.cyborg_count;
+                } else {
+                    arrival.1 += troop.cyborg_count;
+                }
+            }
+        }
+        self.troops = self.troops.iter()
+            .filter(|troop| troop.turn_remaining > 0)
+            .cloned()
+            .collect();
+
+        // Bombs Moving, the enemy bombs are ignored as their target is unknown
+        for bomb in self.bombs.iter_mut() {
+            if bomb.turn_remaining > 0 {
+                bomb.turn_remaining -= 1;
+            }
+        }
+
+        // Orders Execution: bombs, moves then INC. The owner of an order is the owner of its source factory
+        let commands: Vec<String> = self.commands.drain(..).collect();
+        let mut incs = Vec::new();
+        let mut troop_commands = LinkedList::new();
+        troop_commands.append(&mut self.troop_commands);
+        for command in commands.iter() {
+            let words: Vec<&str> = command.split_whitespace().collect();
+            let ids: Vec<i32> = words.iter().skip(1).filter_map(|word| word.parse::<i32>().ok()).collect();
+            let owner = match ids.first().and_then(|id| self.factories.get(id)) {
+                Some(factory) if !factory.is_neutral() => factory.owner,
+                _ => continue
+            };
+
+            match (words[0], ids.len()) {
+                ("BOMB", 2) => {
+                    let distance = self.distance(ids[0], ids[1]);
+                    let id = self.new_entity_id();
+                    self.bombs.push(Bomb{id: id, owner: owner, factory_start: ids[0], factory_end: ids[1], turn_remaining: distance});
+                },
+                ("MOVE", 3) => troop_commands.push_back(Troop{id: 999, owner: owner, factory_start: ids[0], factory_end: ids[1], cyborg_count: ids[2], turn_remaining: 0}),
+                ("INC", 1) => incs.push(ids[0]),
+                _ => {}
+            }
+        }
+
+        // A troop can't take more than the garrison present before the arrivals
+        for mut troop in troop_commands.into_iter() {
+            if troop.factory_start == troop.factory_end { continue }
+            troop.turn_remaining = self.distance(troop.factory_start, troop.factory_end);
+            let factory = self.factories.get_mut(&troop.factory_start).unwrap();
+            if factory.owner != troop.owner { continue }
+            troop.cyborg_count = troop.cyborg_count.min(factory.cyborg_count);
+            if troop.cyborg_count <= 0 { continue }
+
+            factory.cyborg_count -= troop.cyborg_count;
+            troop.id = self.new_entity_id();
+            self.troops.push_back(troop);
+        }
+
+        for id in incs.iter() {
+            self.factories.get_mut(id).unwrap().increase_production();
+        }
+
+        // Production
+        for factory in self.factories.values_mut() {
+            factory.produce();
+        }
+
+        // Combat
+        for (id, &(player_count, enemy_count)) in arrivals.iter() {
+            self.factories.get_mut(id).unwrap().resolve_arrivals(player_count, enemy_count);
+        }
+
+        // Bombs
+        for bomb in self.bombs.iter() {
+            if bomb.turn_remaining == 0 {
+                self.factories.get_mut(&bomb.factory_end).unwrap().explode_bomb();
+            }
+        }
+        self.bombs.retain(|bomb| bomb.turn_remaining != 0);
+
+        self.nb_turn += 1;
+    }
+
+    // Score of a side at the end of the game
+    pub fn total_cyborgs(&self, side: i32) -> i32 {
+        let garrisons: i32 = self.factories.values().filter(|fac| fac.owner == side).map(|fac| fac.cyborg_count).sum();
+        let troops: i32 = self.troops.iter().filter(|troop| troop.owner == side).map(|troop| troop.cyborg_count).sum();
+        return garrisons + troops;
+    }
+
+    // No cyborg left anywhere and no factory to produce more
+    pub fn is_eliminated(&self, side: i32) -> bool {
+        return self.total_cyborgs(side) == 0
+            && !self.factories.values().any(|fac| fac.owner == side && fac.production > 0);
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        return self.nb_turn >= MAX_TURNS || self.is_eliminated(1) || self.is_eliminated(-1);
+    }
+
+    // 1, -1 or 0 for a draw, only meaningful once the state is terminal
+    pub fn winner(&self) -> i32 {
+        match (self.is_eliminated(1), self.is_eliminated(-1)) {
+            (true, false) => return -1,
+            (false, true) => return 1,
+            _ => return (self.total_cyborgs(1) - self.total_cyborgs(-1)).signum()
+        }
+    }
+}
+
+}
+pub mod territory {
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner};
+use crate::trace::{Category, Level};
+
+// Distance to a side that has no factory
+pub const NO_FACTORY: i32 = 999;
+// A neutral is contested when both sides are about as close
+const CONTESTED_MARGIN: i32 = 1;
+// Turns of production counted in the influence of a factory
+const INFLUENCE_PRODUCTION_TURNS: i32 = 5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Zone {
+    Frontline,
+    Backline,
+    Contested
+}
+
+impl Zone {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Zone::Frontline => "frontline",
+            Zone::Backline => "backline",
+            Zone::Contested => "contested"
+        }
+    }
+}
+
+// Friend and enemy are from our point of view, the zone of an owned factory from its owner's:
+// frontline when no ally stands between it and the nearest hostile factory, backline otherwise.
+// A neutral is contested when both sides are as close, backline on our side, frontline on the enemy side.
+#[derive(Clone)]
+pub struct FactoryTerritory {
+    pub friend_distance: i32, // Nearest other factory of ours, NO_FACTORY if none
+    pub enemy_distance: i32, // Nearest other enemy factory, NO_FACTORY if none
+    pub zone: Zone,
+    pub influence: f32 // Garrisons and production around, > 0 when we dominate
+}
+
+#[derive(Clone, Default)]
+pub struct Territory {
+    pub factories: BTreeMap<i32, FactoryTerritory>
+}
+
+impl Territory {
+    pub fn get(&self, id: i32) -> &FactoryTerritory {
+        return self.factories.get(&id).unwrap();
+    }
+
+    pub fn zone(&self, id: i32) -> Zone {
+        return self.get(id).zone;
+    }
+}
+
+impl GameState {
+    pub fn compute_territory(&self) -> Territory {
+        let mut territory = Territory::default();
+        for factory in self.factories.values() {
+            let nearest = |owner: i32| factory.distances.iter()
+                .find(|&&(_, id2)| self.factories.get(&id2).unwrap().owner == owner)
+                .map_or(NO_FACTORY, |&(distance, _)| distance);
+            let friend_distance = nearest(1);
+            let enemy_distance = nearest(-1);
+
+            let zone = if factory.is_neutral() {
+                let gap = enemy_distance - friend_distance;
+                if gap.abs() <= CONTESTED_MARGIN {
+                    Zone::Contested
+                } else if gap > 0 {
+                    Zone::Backline
+                } else {
+                    Zone::Frontline
+                }
+            } else {
+                self.owned_zone(factory.id)
+            };
+
+            // The factory itself counts at distance 0
+            let mut influence = factory.owner as f32 * (factory.cyborg_count + factory.production * INFLUENCE_PRODUCTION_TURNS) as f32;
+            for &(distance, id2) in factory.distances.iter() {
+                let other = self.factories.get(&id2).unwrap();
+                influence += other.owner as f32 * (other.cyborg_count + other.production * INFLUENCE_PRODUCTION_TURNS) as f32 / (distance + 1) as f32;
+            }
+
+            territory.factories.insert(factory.id, FactoryTerritory{friend_distance: friend_distance, enemy_distance: enemy_distance, zone: zone, influence: influence});
+        }
+        return territory;
+    }
+
+    fn owned_zone(&self, id: i32) -> Zone {
+        let factory = self.factories.get(&id).unwrap();
+        let side = factory.owner;
+        let hostile = factory.distances.iter().find(|&&(_, id2)| self.factories.get(&id2).unwrap().owner == -side);
+        let (hostile_distance, hostile_id) = match hostile {
+            Some(&hostile) => hostile,
+            None => return Zone::Backline
+        };
+
+        // An ally closer to the hostile factory and on the way covers this one
+        for &(distance, id2) in factory.distances.iter() {
+            if distance >= hostile_distance { break }
+            if self.factories.get(&id2).unwrap().owner != side { continue }
+            if self.distance(id2, hostile_id) < hostile_distance {
+                return Zone::Backline;
+            }
+        }
+        return Zone::Frontline;
+    }
+
+    pub fn update_territory(&mut self) {
+        self.territory = self.compute_territory();
+        for (id, area) in self.territory.factories.iter() {
+            trace!(Category::Map, Level::Debug, "territory", "factory" => *id, "zone" => area.zone.name(), "friend_distance" => area.friend_distance,
+                   "enemy_distance" => area.enemy_distance, "influence" => area.influence);
+        }
+    }
+}
+
+}
+pub mod logistics {
+use crate::model::{GameState, HasOwner, Troop};
+use crate::territory::{Zone, NO_FACTORY};
+use crate::trace::{Category, Level};
+
+// Fastest routes between factories hopping only through the factories of one side.
+// A troop cannot be redirected, so it is sent to the next hop and forwarded from there on the next turns:
+// it lands at the end of a turn and leaves on the next one, each hop costs one more turn.
+pub struct Routes {
+    length: Vec<Vec<i32>>,
+    hops: Vec<Vec<i32>>,
+    next: Vec<Vec<i32>>
+}
+
+impl Routes {
+    // Sum of the legs plus one turn per hop, NO_FACTORY when there is no route
+    pub fn length(&self, from: i32, to: i32) -> i32 {
+        return self.length[from as usize][to as usize];
+    }
+
+    pub fn next_hop(&self, from: i32, to: i32) -> i32 {
+        return self.next[from as usize][to as usize];
+    }
+}
+
+impl GameState {
+    // Floyd-Warshall, on equal length fewer hops are better
+    pub fn compute_routes(&self, side: i32) -> Routes {
+        let count = self.factories.len();
+        let mut routes = Routes{length: vec![vec![NO_FACTORY; count]; count], hops: vec![vec![0; count]; count], next: vec![vec![-1; count]; count]};
+        for factory in self.factories.values() {
+            let i = factory.id as usize;
+            routes.length[i][i] = 0;
+            routes.next[i][i] = factory.id;
+            for &(distance, id2) in factory.distances.iter() {
+                routes.length[i][id2 as usize] = distance;
+                routes.hops[i][id2 as usize] = 1;
+                routes.next[i][id2 as usize] = id2;
+            }
+        }
+
+        for hop in self.factories.values() {
+            if hop.owner != side { continue }
+            let k = hop.id as usize;
+            for i in 0..count {
+                for j in 0..count {
+                    if i == j || i == k || j == k { continue }
+                    let length = routes.length[i][k] + routes.length[k][j] + 1;
+                    let hops = routes.hops[i][k] + routes.hops[k][j];
+                    if length < routes.length[i][j] || (length == routes.length[i][j] && hops < routes.hops[i][j]) {
+                        routes.length[i][j] = length;
+                        routes.hops[i][j] = hops;
+                        routes.next[i][j] = routes.next[i][k];
+                    }
+                }
+            }
+        }
+        return routes;
+    }
+
+    // Backline factories keep backline_garrison cyborgs and stream the rest to the closest frontline factory
+    pub fn supply_frontline(&mut self) {
+        let frontline: Vec<i32> = self.factories.values()
+            .filter(|factory| factory.is_player() && self.territory.zone(factory.id) == Zone::Frontline)
+            .map(|factory| factory.id)
+            .collect();
+        if frontline.is_empty() { return }
+
+        let routes = self.compute_routes(1);
+        for factory in self.factories.values_mut() {
+            if !factory.is_player() || self.territory.zone(factory.id) != Zone::Backline { continue }
+
+            let surplus = factory.cyborg_remaining - self.params.backline_garrison;
+            if surplus <= 0 { continue }
+
+            // Closest by route, then closest to the enemy
+            let target = *frontline.iter()
+                .min_by_key(|&&id| (routes.length(factory.id, id), self.territory.get(id).enemy_distance, id))
+                .unwrap();
+            if routes.length(factory.id, target) >= NO_FACTORY { continue }
+
+            let hop = routes.next_hop(factory.id, target);
+            factory.cyborg_remaining -= surplus;
+            self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory.id, factory_end: hop, cyborg_count: surplus, turn_remaining: routes.length(factory.id, hop)});
+            trace!(Category::Logistics, Level::Info, "move", "from" => factory.id, "to" => hop, "count" => surplus, "frontline" => target,
+                   "route_length" => routes.length(factory.id, target), "reason" => "backline surplus to the frontline");
+        }
+    }
+}
+
+}
+pub mod threat {
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner};
+use crate::trace::{Category, Level};
+
+// Worst case for one of our factories: every enemy factory within `horizon` launches its whole
+// garrison as late as possible to arrive by turn t, on top of the enemy troops already on their way.
+// Index t of the vectors is the end of turn t, 0 being now. An order given now travels for distance + 1 turns.
+#[derive(Clone)]
+pub struct Threat {
+    pub enemy: Vec<i32>, // Enemy cyborgs arrived by turn t
+    pub support: Vec<i32>, // Our production and troops arrived by turn t, without the garrison
+    pub required_garrison: i32, // Garrison needed now to hold every turn
+    pub deadline: i32 // First turn the current garrison falls short, -1 if it holds
+}
+
+impl Threat {
+    // Missing cyborgs at turn t with the current garrison
+    pub fn deficit(&self, turn: i32, garrison: i32) -> i32 {
+        return self.enemy[turn as usize] - self.support[turn as usize] - garrison;
+    }
+}
+
+impl GameState {
+    // A horizon of 0 only counts the launched attacks
+    pub fn compute_threat(&self, id: i32, horizon: i32) -> Threat {
+        let factory = self.factories.get(&id).unwrap();
+
+        let mut last_turn = horizon;
+        for troop in self.troops.iter() {
+            if troop.factory_end == id { last_turn = last_turn.max(troop.turn_remaining) }
+        }
+
+        let mut threat = Threat{enemy: vec![0; last_turn as usize + 1], support: vec![0; last_turn as usize + 1], required_garrison: 0, deadline: -1};
+        for turn in 1..=last_turn {
+            let t = turn as usize;
+            threat.support[t] = factory.production * (turn - factory.disrupted).max(0);
+
+            for troop in self.troops.iter() {
+                if troop.factory_end != id || troop.turn_remaining > turn { continue }
+                if troop.is_enemy() {
+                    threat.enemy[t] += troop.cyborg_count;
+                } else {
+                    threat.support[t] += troop.cyborg_count;
+                }
+            }
+
+            for &(distance, id2) in factory.distances.iter() {
+                if distance > horizon || distance + 1 > turn { break }
+                let enemy_factory = self.factories.get(&id2).unwrap();
+                if !enemy_factory.is_enemy() { continue }
+                threat.enemy[t] += enemy_factory.cyborg_count + enemy_factory.production * (turn - distance - 1 - enemy_factory.disrupted).max(0);
+            }
+
+            let deficit = threat.deficit(turn, factory.cyborg_count);
+            threat.required_garrison = threat.required_garrison.max(factory.cyborg_count + deficit);
+            if deficit > 0 && threat.deadline == -1 {
+                threat.deadline = turn;
+            }
+        }
+        return threat;
+    }
+
+    pub fn compute_threats(&self, horizon: i32) -> BTreeMap<i32, Threat> {
+        let mut threats = BTreeMap::new();
+        for factory in self.factories.values() {
+            if !factory.is_player() { continue }
+            let threat = self.compute_threat(factory.id, horizon);
+            trace!(Category::Defend, Level::Debug, "threat", "factory" => factory.id, "garrison" => factory.cyborg_count,
+                   "required" => threat.required_garrison, "deadline" => threat.deadline, "enemy" => threat.enemy.clone());
+            threats.insert(factory.id, threat);
+        }
+        return threats;
+    }
+}
+
+}
+pub mod defense {
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner, Troop};
+use crate::threat::Threat;
+use crate::trace::{Category, Level};
+
+// Reinforcements chosen by allocate_defense
+#[derive(Default)]
+pub struct DefensePlan {
+    pub orders: Vec<(i32, i32, i32)>, // (from, to, count)
+    pub abandoned: Vec<i32> // Factories that cannot be saved in time
+}
+
+impl GameState {
+    // Each threatened factory needs its worst deficit, and by each turn t the cyborgs arrived by t must cover
+    // the deficit of turn t. The closest senders first meet every deadline with the fewest cyborgs. The most
+    // productive factories are served first, a factory that cannot be saved is abandoned without using anyone.
+    pub fn allocate_defense(&self, threats: &BTreeMap<i32, Threat>) -> DefensePlan {
+        let mut spare: BTreeMap<i32, i32> = self.factories.values()
+            .filter(|factory| factory.is_player())
+            .map(|factory| (factory.id, factory.cyborg_remaining.max(0)))
+            .collect();
+
+        // (turn, deficit) of the turns the garrison falls short
+        let mut demands: Vec<(i32, Vec<(i32, i32)>)> = Vec::new();
+        for (&id, threat) in threats.iter() {
+            let garrison = self.factories.get(&id).unwrap().cyborg_count;
+            let deficits: Vec<(i32, i32)> = (1..threat.enemy.len() as i32)
+                .map(|turn| (turn, threat.deficit(turn, garrison)))
+                .filter(|&(_, deficit)| deficit > 0)
+                .collect();
+            if !deficits.is_empty() { demands.push((id, deficits)) }
+        }
+        demands.sort_by_key(|&(id, ref deficits)| {
+            let need = deficits.iter().map(|&(_, deficit)| deficit).max().unwrap();
+            (-self.factories.get(&id).unwrap().production, need, id)
+        });
+
+        let mut plan = DefensePlan::default();
+        for (id, deficits) in demands.iter() {
+            let factory = self.factories.get(id).unwrap();
+            let mut need = deficits.iter().map(|&(_, deficit)| deficit).max().unwrap();
+
+            let mut orders = Vec::new();
+            for &(distance, id2) in factory.distances.iter() {
+                if need == 0 { break }
+                let available = spare.get(&id2).cloned().unwrap_or(0);
+                if available == 0 { continue }
+                let count = available.min(need);
+                need -= count;
+                orders.push((id2, distance + 1, count));
+            }
+
+            let in_time = deficits.iter().all(|&(turn, deficit)| {
+                orders.iter().filter(|&&(_, arrival, _)| arrival <= turn).map(|&(_, _, count)| count).sum::<i32>() >= deficit
+            });
+            if need > 0 || !in_time {
+                plan.abandoned.push(*id);
+                continue;
+            }
+
+            for &(from, _, count) in orders.iter() {
+                *spare.get_mut(&from).unwrap() -= count;
+                plan.orders.push((from, *id, count));
+            }
+        }
+        return plan;
+    }
+
+    // Reinforce the factories under attack that can be saved, the others hold until the turn before impact and evacuate
+    pub fn reinforce(&mut self) {
+        let threats = self.compute_threats(0);
+        let plan = self.allocate_defense(&threats);
+        for &(from, to, count) in plan.orders.iter() {
+            let distance = self.distance(from, to);
+            self.factories.get_mut(&from).unwrap().cyborg_remaining -= count;
+            self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: from, factory_end: to, cyborg_count: count, turn_remaining: distance});
+            trace!(Category::Defend, Level::Info, "move", "from" => from, "to" => to, "count" => count, "distance" => distance, "reason" => "reinforce");
+        }
+
+        for &id in plan.abandoned.iter() {
+            let deadline = threats.get(&id).unwrap().deadline;
+            if deadline <= 1 {
+                self.evacuate(id, &plan.abandoned);
+            } else {
+                let factory = self.factories.get_mut(&id).unwrap();
+                trace!(Category::Defend, Level::Info, "abandon", "factory" => id, "garrison" => factory.cyborg_count, "deadline" => deadline,
+                       "reason" => "cannot be saved in time, hold until the turn before impact");
+                factory.cyborg_remaining = 0;
+            }
+        }
+    }
+
+    // Enemy cyborgs left at the arrival of a troop sent now, our troops on their way included
+    pub fn projected_garrison(&self, id: i32, arrival: i32) -> i32 {
+        let factory = self.factories.get(&id).unwrap();
+        let mut garrison = factory.cyborg_count + factory.production * (arrival - factory.disrupted).max(0);
+        for troop in self.troops.iter() {
+            if troop.factory_end != id || troop.turn_remaining > arrival { continue }
+            garrison += troop.cyborg_count * troop.owner * factory.owner;
+        }
+        return garrison;
+    }
+
+    // The whole garrison leaves before the enemy arrives: it captures the most productive enemy factory it can,
+    // or joins the closest factory that is not lost. The factory is traded, not defended.
+    pub fn evacuate(&mut self, id: i32, doomed: &[i32]) {
+        let factory = self.factories.get(&id).unwrap();
+        let garrison = factory.cyborg_count;
+        if garrison <= 0 { return }
+
+        let mut target = None;
+        for &(distance, id2) in factory.distances.iter() {
+            let other = self.factories.get(&id2).unwrap();
+            if !other.is_enemy() || self.projected_garrison(id2, distance + 1) >= garrison { continue }
+            let better = match target {
+                Some((_, production, _)) => other.production > production,
+                None => true
+            };
+            if better {
+                target = Some((id2, other.production, "counterattack"));
+            }
+        }
+        if target.is_none() {
+            target = factory.distances.iter()
+                .find(|&&(_, id2)| self.factories.get(&id2).unwrap().is_player() && !doomed.contains(&id2))
+                .map(|&(_, id2)| (id2, 0, "join the closest safe factory"));
+        }
+
+        let (to, _, reason) = match target {
+            Some(target) => target,
+            None => return
+        };
+        let distance = self.distance(id, to);
+        let production = factory.production;
+        self.factories.get_mut(&id).unwrap().cyborg_remaining = 0;
+        self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: id, factory_end: to, cyborg_count: garrison, turn_remaining: distance});
+        trace!(Category::Defend, Level::Info, "trade", "factory" => id, "production" => production, "to" => to, "count" => garrison, "reason" => reason);
+    }
+}
+
+}
+pub mod counter {
+use crate::model::{GameState, HasOwner, Troop};
+use crate::trace::{Category, Level};
+
+impl GameState {
+    // Cyborgs an enemy factory launched since the last turn, its troops still at the full distance
+    pub fn just_launched(&self, id: i32) -> i32 {
+        return self.troops.iter()
+            .filter(|troop| troop.is_enemy() && troop.factory_start == id && troop.turn_remaining == self.distance(troop.factory_start, troop.factory_end))
+            .map(|troop| troop.cyborg_count)
+            .sum();
+    }
+
+    // Worst case garrison when a troop sent now arrives: the projected garrison plus every enemy factory that could reinforce it first
+    pub fn counter_garrison(&self, id: i32, arrival: i32) -> i32 {
+        return self.projected_garrison(id, arrival) + self.enemy_reinforcements(id, arrival);
+    }
+
+    // Garrisons of the enemy factories whose troops sent now land before `arrival`
+    pub fn enemy_reinforcements(&self, id: i32, arrival: i32) -> i32 {
+        let mut reinforcements = 0;
+        for &(distance, id2) in self.factories.get(&id).unwrap().distances.iter() {
+            if distance + 1 >= arrival { break }
+            let other = self.factories.get(&id2).unwrap();
+            if other.is_enemy() { reinforcements += other.cyborg_count }
+        }
+        return reinforcements;
+    }
+
+    // An enemy factory that launched more than it kept is hit from the closest factory able to take it before any help comes
+    pub fn counter_attack(&mut self) {
+        let mut targets: Vec<(i32, i32)> = self.factories.values()
+            .filter(|factory| factory.is_enemy())
+            .map(|factory| (factory.id, self.just_launched(factory.id)))
+            .filter(|&(id, launched)| launched > 0 && launched >= self.factories.get(&id).unwrap().cyborg_count)
+            .collect();
+        targets.sort_by_key(|&(id, _)| (-self.factories.get(&id).unwrap().production, id));
+
+        for &(id, launched) in targets.iter() {
+            let mut order = None;
+            for &(distance, id2) in self.factories.get(&id).unwrap().distances.iter() {
+                let source = self.factories.get(&id2).unwrap();
+                if !source.is_player() { continue }
+                let count = self.counter_garrison(id, distance + 1) + 1;
+                if count <= source.cyborg_remaining {
+                    order = Some((id2, distance, count));
+                    break;
+                }
+            }
+
+            let (from, distance, count) = match order {
+                Some(order) => order,
+                None => continue
+            };
+            self.factories.get_mut(&from).unwrap().cyborg_remaining -= count;
+            self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: from, factory_end: id, cyborg_count: count, turn_remaining: distance});
+            trace!(Category::Attack, Level::Info, "move", "from" => from, "to" => id, "count" => count, "launched" => launched,
+                   "reason" => "counterattack a factory the enemy just emptied");
+        }
+    }
+}
+
+}
+pub mod profile {
+use std::rc::Rc;
+
+use crate::model::{GameState, HasOwner};
+use crate::trace::{Category, Level};
+
+// Turns where the targets of the opponent tell if it expands to the neutrals first
+const OPENING_TURNS: i32 = 5;
+
+// Habits of the opponent, counted from the differences between two consecutive turns
+#[derive(Clone, Default)]
+pub struct OpponentProfile {
+    pub turns: i32, // Turns observed
+    pub bombs: i32,
+    pub launches: i32, // New enemy troops
+    pub launched_share: f32, // Sum over the launches of the part of the source garrison sent
+    pub incs: i32,
+    pub neutral_launches: i32, // Launches toward a neutral factory
+    pub opening_launches: i32,
+    pub opening_neutral_launches: i32
+}
+
+impl OpponentProfile {
+    pub fn bomb_rate(&self) -> f32 {
+        return if self.turns > 0 { self.bombs as f32 / self.turns as f32 } else { 0.0 };
+    }
+
+    // Mean part of its garrison a factory sends in one troop, 1.0 is an all-in
+    pub fn attack_size(&self) -> f32 {
+        return if self.launches > 0 { self.launched_share / self.launches as f32 } else { 0.0 };
+    }
+
+    pub fn inc_rate(&self) -> f32 {
+        return if self.turns > 0 { self.incs as f32 / self.turns as f32 } else { 0.0 };
+    }
+
+    pub fn neutral_share(&self) -> f32 {
+        return if self.launches > 0 { self.neutral_launches as f32 / self.launches as f32 } else { 0.0 };
+    }
+
+    // Most of the opening launches went to the neutrals
+    pub fn neutrals_first(&self) -> bool {
+        return self.opening_launches > 0 && 2 * self.opening_neutral_launches > self.opening_launches;
+    }
+}
+
+impl GameState {
+    // Compare the turn read from the referee with the previous one, then keep it for the next turn
+    pub fn update_profile(&mut self) {
+        let last = match self.last_seen.take() {
+            Some(last) => last,
+            None => {
+                self.last_seen = Some(Rc::new(self.snapshot()));
+                return;
+            }
+        };
+        self.update_enemy_actions(&last);
+        self.check_simulation(&last);
+
+        let profile = &mut self.profile;
+        profile.turns += 1;
+        profile.bombs += self.enemy_actions.bombs.len() as i32;
+        profile.incs += self.enemy_actions.incs.len() as i32;
+        for &(from, to, count) in self.enemy_actions.moves.iter() {
+            let source = last.factories.get(&from).unwrap();
+            let target = last.factories.get(&to).unwrap();
+
+            profile.launches += 1;
+            if source.cyborg_count > 0 {
+                profile.launched_share += (count as f32 / source.cyborg_count as f32).min(1.0);
+            }
+            if target.is_neutral() { profile.neutral_launches += 1 }
+            if self.nb_turn <= OPENING_TURNS {
+                profile.opening_launches += 1;
+                if target.is_neutral() { profile.opening_neutral_launches += 1 }
+            }
+        }
+
+        trace!(Category::Opponent, Level::Debug, "profile", "turns" => profile.turns, "bomb_rate" => profile.bomb_rate(),
+               "attack_size" => profile.attack_size(), "inc_rate" => profile.inc_rate(), "neutral_share" => profile.neutral_share(),
+               "neutrals_first" => profile.neutrals_first());
+        self.last_seen = Some(Rc::new(self.snapshot()));
+    }
+}
+
+}
+pub mod inference {
+use std::collections::BTreeSet;
+
+use crate::golden::diff_entities;
+use crate::model::{GameState, HasOwner, Snapshot, Troop, BOMB_DISRUPTION};
+use crate::trace::{Category, Level};
+
+// Enemy commands of the last turn, rebuilt from what they changed. The simulation of the last turn with
+// these commands and ours must give the current turn, any difference is a simulator discrepancy.
+#[derive(Clone, Default)]
+pub struct EnemyActions {
+    pub moves: Vec<(i32, i32, i32)>, // (from, to, count) of the new enemy troops
+    pub incs: Vec<i32>, // Enemy factories one production higher
+    pub bombs: Vec<i32>, // Sources of the new enemy bombs, the target stays hidden
+    pub explosions: Vec<i32>, // Factories disrupted again when an enemy bomb disappeared
+    pub discrepancies: Vec<String>
+}
+
+impl GameState {
+    // The last turn as the simulator sees it: its entities and our orders
+    pub fn restore(&self, last: &Snapshot) -> GameState {
+        let mut state = self.clone();
+        state.nb_turn = last.nb_turn;
+        state.factories = last.factories.clone();
+        state.troops = last.troops.clone();
+        state.bombs = last.bombs.clone();
+        state.commands.clear();
+        state.troop_commands.clear();
+        for order in last.orders.iter() {
+            state.push_order(1, order);
+        }
+        return state;
+    }
+
+    pub fn infer_enemy_actions(&self, last: &Snapshot) -> EnemyActions {
+        let mut actions = EnemyActions::default();
+
+        let known_troops: BTreeSet<i32> = last.troops.iter().map(|troop| troop.id).collect();
+        for troop in self.troops.iter() {
+            if troop.is_enemy() && !known_troops.contains(&troop.id) {
+                actions.moves.push((troop.factory_start, troop.factory_end, troop.cyborg_count));
+            }
+        }
+
+        for factory in self.factories.values() {
+            let before = last.factories.get(&factory.id).unwrap();
+            if factory.is_enemy() && before.is_enemy() && factory.production == before.production + 1 {
+                actions.incs.push(factory.id);
+            }
+        }
+
+        let known_bombs: BTreeSet<i32> = last.bombs.iter().map(|bomb| bomb.id).collect();
+        let bombs: BTreeSet<i32> = self.bombs.iter().map(|bomb| bomb.id).collect();
+        actions.bombs = self.bombs.iter()
+            .filter(|bomb| bomb.is_enemy() && !known_bombs.contains(&bomb.id))
+            .map(|bomb| bomb.factory_start)
+            .collect();
+        let exploded = last.bombs.iter().filter(|bomb| bomb.is_enemy() && !bombs.contains(&bomb.id)).count();
+
+        let mut predicted = self.restore(last);
+        for &(from, to, count) in actions.moves.iter() {
+            predicted.troop_commands.push_back(Troop{id: 999, owner: -1, factory_start: from, factory_end: to, cyborg_count: count, turn_remaining: 0});
+        }
+        for &id in actions.incs.iter() {
+            predicted.commands.push(format!("INC {}", id));
+        }
+        predicted.sim_next_turn();
+
+        // The explosions come last, their targets are the factories newly disrupted
+        for factory in self.factories.values() {
+            if actions.explosions.len() == exploded { break }
+            let simulated = predicted.factories.get_mut(&factory.id).unwrap();
+            if factory.disrupted == BOMB_DISRUPTION && simulated.disrupted != BOMB_DISRUPTION {
+                simulated.explode_bomb();
+                actions.explosions.push(factory.id);
+            }
+        }
+
+        actions.discrepancies = predicted.discrepancies(self);
+        return actions;
+    }
+
+    // Factories and troops of `seen` that differ from this simulated state, new troops are matched by content
+    pub fn discrepancies(&self, seen: &GameState) -> Vec<String> {
+        let mut discrepancies = Vec::new();
+        for (id, factory) in seen.factories.iter() {
+            let simulated = self.factories.get(id).unwrap();
+            let summary = (factory.owner, factory.cyborg_count, factory.production, factory.disrupted);
+            let simulated_summary = (simulated.owner, simulated.cyborg_count, simulated.production, simulated.disrupted);
+            if summary != simulated_summary {
+                discrepancies.push(format!("factory {} {:?} simulated {:?}", id, summary, simulated_summary));
+            }
+        }
+
+        let troop_summary = |troop: &Troop| (troop.owner, troop.factory_start, troop.factory_end, troop.cyborg_count, troop.turn_remaining);
+        let seen_troops: Vec<_> = seen.troops.iter().map(troop_summary).collect();
+        let simulated_troops: Vec<_> = self.troops.iter().map(troop_summary).collect();
+        let (missing, unexpected) = diff_entities(&seen_troops, &simulated_troops);
+        for troop in missing.iter() {
+            discrepancies.push(format!("troop {:?} not simulated", troop));
+        }
+        for troop in unexpected.iter() {
+            discrepancies.push(format!("troop {:?} simulated only", troop));
+        }
+        return discrepancies;
+    }
+
+    // Called by update_profile with the previous turn
+    pub fn update_enemy_actions(&mut self, last: &Snapshot) {
+        let actions = self.infer_enemy_actions(last);
+        self.enemy_bomb_count -= actions.bombs.len() as i32;
+
+        trace!(Category::Opponent, Level::Info, "actions", "moves" => actions.moves.iter().map(|&(from, to, count)| format!("{} {} {}", from, to, count)).collect::<Vec<_>>(),
+               "incs" => actions.incs.clone(), "bombs" => actions.bombs.clone(), "explosions" => actions.explosions.clone(), "bombs_left" => self.enemy_bomb_count);
+        for discrepancy in actions.discrepancies.iter() {
+            trace!(Category::Opponent, Level::Info, "discrepancy", "turn" => last.nb_turn, "detail" => discrepancy.clone());
+        }
+        self.enemy_actions = actions;
+    }
+}
+
+}
+pub mod simcheck {
+use std::collections::BTreeSet;
+
+use crate::model::{GameState, HasOwner, Snapshot, BOMB_DISRUPTION};
+use crate::trace::{trace_enabled, Category, Level};
+
+impl GameState {
+    // Simulate the last turn with our orders only and compare with the current turn what the enemy orders
+    // of that turn cannot change: our troops, the enemy troops launched before, and the factories that were
+    // not the enemy's and were not hit by an enemy bomb. Any difference is a rule of the simulator gone wrong.
+    pub fn sim_check(&self, last: &Snapshot) -> Vec<String> {
+        let mut predicted = self.restore(last);
+        predicted.sim_next_turn();
+
+        let known_troops: BTreeSet<i32> = last.troops.iter().map(|troop| troop.id).collect();
+        let mut seen = self.clone();
+        seen.troops = self.troops.iter()
+            .filter(|troop| troop.is_player() || known_troops.contains(&troop.id))
+            .cloned()
+            .collect();
+        seen.factories.retain(|id, factory| {
+            let simulated = predicted.factories.get(id).unwrap();
+            let bombed = factory.disrupted == BOMB_DISRUPTION && simulated.disrupted != BOMB_DISRUPTION;
+            !last.factories.get(id).unwrap().is_enemy() && !bombed
+        });
+        return predicted.discrepancies(&seen);
+    }
+
+    // Online check of the simulator, on with GITC_TRACE="sim=info"
+    pub fn check_simulation(&self, last: &Snapshot) {
+        if !trace_enabled(Category::Sim, Level::Info) { return }
+        let mismatches = self.sim_check(last);
+        if !mismatches.is_empty() {
+            trace!(Category::Sim, Level::Info, "mismatch", "turn" => last.nb_turn, "count" => mismatches.len(), "mismatches" => mismatches);
+        }
+    }
+}
+
+}
+pub mod tracking {
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner};
+use crate::trace::{Category, Level};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Launch {
+    Ours,
+    Enemy, // Inferred from a new enemy troop
+    InFlight // Already on its way when the tracking started
+}
+
+// A troop followed by its entity id from its first turn to its arrival
+#[derive(Clone, PartialEq, Debug)]
+pub struct TrackedTroop {
+    pub owner: i32,
+    pub factory_start: i32,
+    pub factory_end: i32,
+    pub cyborg_count: i32,
+    pub launch_turn: i32, // Turn of the MOVE order
+    pub arrival_turn: i32, // First turn the troop is gone and its fight is in the input
+    pub launch: Launch
+}
+
+impl TrackedTroop {
+    pub fn travel_turns(&self) -> i32 {
+        return self.arrival_turn - self.launch_turn;
+    }
+}
+
+impl GameState {
+    // Match the troops of this turn with the tracked ones, a troop that does not move by one turn is a simulator mismatch
+    pub fn update_tracked_troops(&mut self) {
+        let started = self.last_seen.is_some();
+        let mut tracked = BTreeMap::new();
+        for troop in self.troops.iter() {
+            let distance = self.distance(troop.factory_start, troop.factory_end);
+            let record = match self.tracked_troops.remove(&troop.id) {
+                Some(record) => record,
+                None => TrackedTroop{owner: troop.owner, factory_start: troop.factory_start, factory_end: troop.factory_end, cyborg_count: troop.cyborg_count,
+                                     launch_turn: self.nb_turn - 1 - (distance - troop.turn_remaining), arrival_turn: self.nb_turn + troop.turn_remaining,
+                                     launch: if !started { Launch::InFlight } else if troop.is_player() { Launch::Ours } else { Launch::Enemy }}
+            };
+
+            if record.arrival_turn - self.nb_turn != troop.turn_remaining || record.cyborg_count != troop.cyborg_count {
+                trace!(Category::Sim, Level::Info, "troop_progress", "id" => troop.id, "expected_turns" => record.arrival_turn - self.nb_turn,
+                       "turns" => troop.turn_remaining, "expected_count" => record.cyborg_count, "count" => troop.cyborg_count);
+            }
+            tracked.insert(troop.id, record);
+        }
+
+        for (id, record) in self.tracked_troops.iter() {
+            if record.arrival_turn > self.nb_turn {
+                trace!(Category::Sim, Level::Info, "troop_progress", "id" => *id, "expected_turns" => record.arrival_turn - self.nb_turn, "turns" => 0);
+            }
+        }
+        self.tracked_troops = tracked;
+    }
+
+    // Cyborgs of `side` on their way to `id`
+    pub fn committed(&self, side: i32, id: i32) -> i32 {
+        return self.tracked_troops.values()
+            .filter(|record| record.owner == side && record.factory_end == id)
+            .map(|record| record.cyborg_count)
+            .sum();
+    }
+}
+
+}
+pub mod allocation {
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner, Troop};
+use crate::territory::Zone;
+use crate::threat::Threat;
+use crate::trace::{Category, Level};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Purpose {
+    Capture,
+    Reinforce,
+    Feint // One cyborg to an enemy factory, worth feint_value
+}
+
+impl Purpose {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Purpose::Capture => "capture",
+            Purpose::Reinforce => "reinforce",
+            Purpose::Feint => "feint"
+        }
+    }
+}
+
+// One possible order of a factory, its value uses the evaluator weights
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub purpose: Purpose,
+    pub to: i32,
+    pub count: i32,
+    pub value: f32
+}
+
+// Candidates to take within `spare` cyborgs and `max_orders` orders for the most value, a knapsack on both
+pub fn best_split(candidates: &[Candidate], spare: i32, max_orders: i32) -> Vec<usize> {
+    let (spare, max_orders) = (spare.max(0) as usize, max_orders.max(0) as usize);
+    // best[i][c][o]: value of the first i candidates with c cyborgs and o orders at most
+    let mut best = vec![vec![vec![0.0f32; max_orders + 1]; spare + 1]; candidates.len() + 1];
+    for (i, candidate) in candidates.iter().enumerate() {
+        let count = candidate.count as usize;
+        for c in 0..=spare {
+            for o in 0..=max_orders {
+                best[i + 1][c][o] = best[i][c][o];
+                if count <= c && o > 0 && best[i][c - count][o - 1] + candidate.value > best[i + 1][c][o] {
+                    best[i + 1][c][o] = best[i][c - count][o - 1] + candidate.value;
+                }
+            }
+        }
+    }
+
+    let mut chosen = Vec::new();
+    let (mut c, mut o) = (spare, max_orders);
+    for i in (0..candidates.len()).rev() {
+        if best[i + 1][c][o] != best[i][c][o] {
+            chosen.push(i);
+            c -= candidates[i].count as usize;
+            o -= 1;
+        }
+    }
+    chosen.reverse();
+    return chosen;
+}
+
+impl GameState {
+    // Owner and garrison a troop sent now meets, after the production and the troops landing first
+    pub fn garrison_at_arrival(&self, id: i32, arrival: i32) -> (i32, i32) {
+        let mut factory = self.factories.get(&id).unwrap().clone();
+        for turn in 1..=arrival {
+            factory.produce();
+            let (mut player_count, mut enemy_count) = (0, 0);
+            for troop in self.troops.iter() {
+                if troop.factory_end != id || troop.turn_remaining != turn { continue }
+                if troop.is_player() {
+                    player_count += troop.cyborg_count;
+                } else {
+                    enemy_count += troop.cyborg_count;
+                }
+            }
+            factory.resolve_arrivals(player_count, enemy_count);
+        }
+        return (factory.owner, factory.cyborg_count);
+    }
+
+    // Orders worth sending from `from`, the cyborgs needed are counted at the arrival
+    pub fn move_candidates(&self, from: i32, threats: &BTreeMap<i32, Threat>) -> Vec<Candidate> {
+        let weights = self.params.eval;
+        let factory = self.factories.get(&from).unwrap();
+        let mut candidates = Vec::new();
+        for &(distance, id) in factory.distances.iter() {
+            let target = self.factories.get(&id).unwrap();
+            let arrival = distance + 1;
+            // Production lasts until the lookahead
+            let discount = (self.params.lookahead - arrival).max(0) as f32 / self.params.lookahead as f32;
+
+            // The garrison killed of an enemy factory is an even trade, its production changes side
+            let enemy_value = 2.0 * weights.production * target.production as f32 * discount;
+            if target.is_neutral() && target.production > 0 {
+                match self.garrison_at_arrival(id, arrival) {
+                    (0, garrison) => {
+                        let value = weights.production * target.production as f32 * discount - weights.owned_cyborgs * garrison as f32;
+                        candidates.push(Candidate{purpose: Purpose::Capture, to: id, count: garrison + 1, value: value});
+                    },
+                    // An enemy troop takes it first
+                    (-1, garrison) => {
+                        let count = garrison + self.enemy_reinforcements(id, arrival) + 1;
+                        candidates.push(Candidate{purpose: Purpose::Capture, to: id, count: count, value: enemy_value});
+                    },
+                    _ => ()
+                }
+            } else if target.is_enemy() {
+                let count = self.counter_garrison(id, arrival) + 1;
+                if count > 0 && target.production > 0 {
+                    candidates.push(Candidate{purpose: Purpose::Capture, to: id, count: count, value: enemy_value});
+                }
+                if self.params.feint_value > weights.owned_cyborgs {
+                    candidates.push(Candidate{purpose: Purpose::Feint, to: id, count: 1, value: self.params.feint_value - weights.owned_cyborgs});
+                }
+            } else if let Some(threat) = threats.get(&id) {
+                let need = threat.required_garrison - target.cyborg_count;
+                if need > 0 && arrival <= threat.deadline {
+                    candidates.push(Candidate{purpose: Purpose::Reinforce, to: id, count: need, value: 2.0 * weights.production * target.production as f32 * discount});
+                }
+            }
+        }
+        candidates.retain(|candidate| candidate.value > 0.0);
+        return candidates;
+    }
+
+    // Split the spare cyborgs of each frontline factory among several targets, the biggest spares choose first
+    // and a target is only served once
+    pub fn allocate_moves(&mut self) {
+        if self.params.max_orders == 0 { return }
+        let threats = self.compute_threats(self.params.threat_horizon);
+        let mut sources: Vec<(i32, i32)> = self.factories.values()
+            .filter(|factory| factory.is_player() && factory.cyborg_remaining > 0 && self.territory.zone(factory.id) != Zone::Backline)
+            .map(|factory| (factory.id, factory.cyborg_remaining))
+            .collect();
+        sources.sort_by_key(|&(id, spare)| (-spare, id));
+
+        let mut served: Vec<i32> = self.troop_commands.iter().map(|troop| troop.factory_end).collect();
+        for &(from, spare) in sources.iter() {
+            let candidates: Vec<Candidate> = self.move_candidates(from, &threats).into_iter()
+                .filter(|candidate| !served.contains(&candidate.to))
+                .collect();
+
+            for i in best_split(&candidates, spare, self.params.max_orders) {
+                let candidate = &candidates[i];
+                served.push(candidate.to);
+                self.factories.get_mut(&from).unwrap().cyborg_remaining -= candidate.count;
+                self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: from, factory_end: candidate.to, cyborg_count: candidate.count, turn_remaining: self.distance(from, candidate.to)});
+                trace!(Category::Attack, Level::Info, "move", "from" => from, "to" => candidate.to, "count" => candidate.count, "value" => candidate.value,
+                       "reason" => candidate.purpose.name());
+            }
+        }
+    }
+}
+
+}
+pub mod strategy {
+use std::rc::Rc;
+
+use crate::model::{Factory, GameState, HasOwner, Troop};
+use crate::territory::Zone;
+use crate::trace::{trace_enabled, trace_turn, Category, Level};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Brain {
+    Rules,
+    RandomSearch
+}
+
+impl GameState {
+    // Orders are left in troop_commands and commands
+    pub fn play_turn(&mut self, brain: Brain) {
+        trace_turn(self.nb_turn);
+        self.update_territory();
+        self.update_tracked_troops();
+        self.update_profile();
+        if trace_enabled(Category::Turn, Level::Debug) {
+            self.print_factories();
+            for troop in self.troops.iter() {
+                trace!(Category::Turn, Level::Debug, "troop", "id" => troop.id, "owner" => troop.owner, "from" => troop.factory_start,
+                       "to" => troop.factory_end, "count" => troop.cyborg_count, "turns" => troop.turn_remaining,
+                       "launched" => self.tracked_troops.get(&troop.id).unwrap().launch_turn);
             }
+        }
+
+        match brain {
+            Brain::Rules => self.defend_strategy(),
+            Brain::RandomSearch => *self = self.random_search()
+        }
+        self.compute_bomb();
 
+        let mut orders = self.commands.clone();
+        for troop in self.troop_commands.iter() {
+            orders.push(format!("MOVE {} {} {}", troop.factory_start, troop.factory_end, troop.cyborg_count));
+        }
+        trace!(Category::Turn, Level::Info, "orders", "orders" => orders.clone());
+        // Kept to simulate this turn once the next one is known
+        if let Some(last) = self.last_seen.as_mut() {
+            Rc::make_mut(last).orders = orders;
         }
     }
 
-    fn random_strategy(&mut self) {
+    pub fn random_strategy(&mut self) {
         let factory_count: i32 = self.factories.len() as i32;
-        for (id, factory) in  &mut self.factories {
+        for factory in self.factories.values_mut() {
             if !factory.is_player() { continue }
 
-            let cyborg_count = rand::thread_rng().gen_range(0, factory.cyborg_remaining + 1);
+            let cyborg_count = self.rng.gen_range(0, factory.cyborg_remaining + 1);
 
             if cyborg_count == 0 { continue }
 
             factory.cyborg_remaining -= cyborg_count;
 
-            let mut target = rnd_range(self.start, factory_count);
+            let mut target = self.rng.gen_range(0, factory_count);
 
             while target == factory.id {
-                target = rnd_range(self.start, factory_count);
+                target = self.rng.gen_range(0, factory_count);
             }
 
             self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory.id, factory_end: target, cyborg_count: cyborg_count, turn_remaining: 10});
@@ -177,15 +2109,15 @@ impl GameState {
 
     }
 
-    fn max_strategy(&mut self) {
+    pub fn max_strategy(&mut self) {
 
         let max_factory_option = self.factories.iter()
-            .filter(|&(ind, fac)| fac.is_player())
-            .max_by_key(|&(ind, fac)| fac.cyborg_remaining);
+            .filter(|&(_, fac)| fac.is_player())
+            .max_by_key(|&(_, fac)| fac.cyborg_remaining);
 
-        if !max_factory_option.is_some() { return }
+        if max_factory_option.is_none() { return }
 
-        let mut max_factory = max_factory_option.unwrap().1;
+        let max_factory = max_factory_option.unwrap().1;
 
         // Closest factory
         for &(distance, id2) in max_factory.distances.iter() {
@@ -193,13 +2125,15 @@ impl GameState {
             if !factory2.is_player() && factory2.production > 0 {
                 //self.commands.push(format!("MOVE {} {} {}", max_factory.id, id2, max_factory.cyborg_count));
                 self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: max_factory.id, factory_end: id2, cyborg_count: max_factory.cyborg_remaining, turn_remaining: distance});
+                trace!(Category::Attack, Level::Info, "move", "from" => max_factory.id, "to" => id2, "count" => max_factory.cyborg_remaining,
+                       "reason" => "biggest garrison to the closest producing factory");
                 break;
             }
         }
 
     }
 
-    fn targeted_attack_strategy(&mut self) {
+    pub fn targeted_attack_strategy(&mut self) {
 
         /*
         { // Dont attack if prod not full
@@ -212,21 +2146,25 @@ impl GameState {
         }
     */
         let mut min_dist = 999;
-        let mut target: i32 = -1 as i32;
+        let mut target: i32 = -1;
         let factories_immu = self.factories.clone();
         for (id, enemy_fac) in self.factories.iter() {
             if !enemy_fac.is_enemy() { continue }
             if enemy_fac.production == 0 { continue }
+            if self.budget.should_stop() {
+                trace!(Category::Timing, Level::Info, "deadline", "strategy" => "attack", "remaining_ms" => self.budget.remaining().as_millis() as i32);
+                break;
+            }
 
             let mut turn = -1;
             let mut state = self.clone();
-            while !state.factories.get(&id).unwrap().is_player() && turn < 20 {
+            while !state.factories.get(id).unwrap().is_player() && turn < self.params.lookahead && !state.is_terminal() {
                 //print_err!("turn : {} id{} owner{}", turn, state.factories.get(&id).unwrap().id, state.factories.get(&id).unwrap().owner);
                 state.sim_next_turn();
                 turn += 1;
             }
-            if turn < 20 {
-                print_err!("[ATTACK] Will be captured by me id: {} in {} turn", id, turn);
+            if state.factories.get(id).unwrap().is_player() {
+                trace!(Category::Attack, Level::Debug, "skip_target", "factory" => id, "captured_in" => turn, "reason" => "already captured by our troops");
                 continue;
             }
 
@@ -241,6 +2179,7 @@ impl GameState {
 
             }
 
+            trace!(Category::Attack, Level::Debug, "candidate", "factory" => enemy_fac.id, "sum_distance" => sum_dist);
             if min_dist > sum_dist {
                 min_dist = sum_dist;
                 target = enemy_fac.id;
@@ -249,13 +2188,16 @@ impl GameState {
 
         if target == -1 { return }
 
-        print_err!("Targeting attack to {}", target);
+        trace!(Category::Attack, Level::Info, "target", "factory" => target, "sum_distance" => min_dist);
 
-        for (id, fac) in self.factories.iter_mut() {
+        for fac in self.factories.values_mut() {
             if !fac.is_player() { continue }
+            // The backline feeds the frontline instead, see supply_frontline
+            if self.territory.zone(fac.id) == Zone::Backline { continue }
 
             if fac.cyborg_remaining > 0 {
                 self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: fac.id, factory_end: target, cyborg_count: fac.cyborg_remaining, turn_remaining: 999});
+                trace!(Category::Attack, Level::Info, "move", "from" => fac.id, "to" => target, "count" => fac.cyborg_remaining, "reason" => "targeted attack");
                 fac.cyborg_remaining = 0;
             }
 
@@ -263,9 +2205,9 @@ impl GameState {
 
     }
 
-    fn neutral_first_strategy(&mut self) {
+    pub fn neutral_first_strategy(&mut self) {
         let factories_immu = self.factories.clone();
-        for (id, factory) in self.factories.iter_mut() {
+        for factory in self.factories.values_mut() {
 
             if factory.is_player() {
                 for &(distance, id2) in factory.distances.iter() {
@@ -273,28 +2215,17 @@ impl GameState {
                     if fac_target.production > 0 {
 
                         if fac_target.is_neutral() && fac_target.cyborg_count < factory.cyborg_remaining {
-                            let mut is_enemy_closest = false;
-                            // Check if fac_target is the closest
-                            for &(distance, id2) in fac_target.distances.iter() {
-
-                                if id2 == factory.id {
-                                    is_enemy_closest = false;
-                                    break;
-                                }
-
-                                let fac = factories_immu.get(&id2).unwrap();
-                                if fac.is_enemy() {
-                                    is_enemy_closest = true;
-                                    break;
-                                }
-
-                            }
+                            let is_enemy_closest = self.territory.get(fac_target.id).enemy_distance < distance;
                             if !is_enemy_closest {
                                 if self.bombing {
                                     self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory.id, factory_end: fac_target.id, cyborg_count: factory.cyborg_count, turn_remaining: distance});
+                                    trace!(Category::Neutral, Level::Info, "move", "from" => factory.id, "to" => fac_target.id, "count" => factory.cyborg_count,
+                                           "reason" => "bomb incoming, evacuate to the neutral");
                                     factory.cyborg_remaining -= factory.cyborg_count;
                                 } else {
                                     self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory.id, factory_end: fac_target.id, cyborg_count: fac_target.cyborg_count +1, turn_remaining: distance});
+                                    trace!(Category::Neutral, Level::Info, "move", "from" => factory.id, "to" => fac_target.id, "count" => fac_target.cyborg_count + 1,
+                                           "reason" => "neutral closer to us than to the enemy");
                                     factory.cyborg_remaining -= fac_target.cyborg_count +1;
                                 }
 
@@ -306,17 +2237,17 @@ impl GameState {
 
         }
 
-        if self.troop_commands.len() == 0 {
+        if self.troop_commands.is_empty() {
             self.targeted_attack_strategy();
         }
 
     }
 
-    fn compute_inc(&mut self) {
+    pub fn compute_inc(&mut self) {
         if self.nb_turn == 0 { return }
 
         let factories_immu = self.factories.clone();
-        let inc_threshold = 15;
+        let inc_threshold = self.params.inc_threshold;
         let mut troops_sent = Vec::new();
         for (id, factory) in self.factories.iter_mut() {
             if !factory.is_player() { continue }
@@ -325,6 +2256,7 @@ impl GameState {
             if factory.cyborg_remaining > inc_threshold {
                 factory.cyborg_remaining -= 10;
                 self.commands.push(format!("INC {}", id));
+                trace!(Category::Inc, Level::Info, "inc", "factory" => id, "remaining" => factory.cyborg_remaining);
             } else {
 
                 // Find a possible troop donator to Inc
@@ -332,12 +2264,13 @@ impl GameState {
 
                     if factory.production * distance > inc_threshold - factory.cyborg_remaining { break }
 
-                    let mut factory_renfort = factories_immu.get(&id2).unwrap();
+                    let factory_renfort = factories_immu.get(&id2).unwrap();
 
                     if !factory_renfort.is_player() { continue }
                     if factory_renfort.production < 3 { continue }
                     if inc_threshold - factory.cyborg_remaining < factory_renfort.cyborg_remaining {
-                        print_err!("Send help troop to increase {} from {}", factory.id, factory_renfort.id);
+                        trace!(Category::Inc, Level::Info, "help", "factory" => factory.id, "from" => factory_renfort.id,
+                               "count" => inc_threshold - factory.cyborg_remaining, "reason" => "reach the INC threshold");
                         self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory_renfort.id, factory_end: factory.id, cyborg_count: inc_threshold - factory.cyborg_remaining, turn_remaining: distance});
                         troops_sent.push(Troop{id: 999, owner: 1, factory_start: factory_renfort.id, factory_end: factory.id, cyborg_count: inc_threshold - factory.cyborg_remaining, turn_remaining: distance});
                         // factory_renfort.cyborg_remaining -= 15 - factory.cyborg_remaining
@@ -349,115 +2282,48 @@ impl GameState {
             }
         }
         for troop in troops_sent.iter() {
-            let mut factory_renfort = self.factories.get_mut(&troop.factory_start).unwrap();
+            let factory_renfort = self.factories.get_mut(&troop.factory_start).unwrap();
             factory_renfort.cyborg_remaining -= troop.cyborg_count;
         }
 
     }
 
-    fn defend_strategy(&mut self) {
-
-        // Check if being attacked -> no move
-        for (id, factory) in self.factories.iter_mut() {
-            if !factory.is_player() { continue }
-
-            let mut enemy_count = 0;
-            for troop in self.troops.iter() {
-                if troop.factory_end == factory.id {
-                    if troop.is_enemy() {
-                        enemy_count += troop.cyborg_count;
-                    }
-                }
-            }
-            if enemy_count >= factory.cyborg_count {
-                print_err!("Need defense id:{}", factory.id);
-                factory.cyborg_remaining = 0;
-            } else {
-                factory.cyborg_remaining -= enemy_count;
-            }
-        }
-
-        // Support another factory
-        for (id, factory) in self.factories.iter() {
-            if !factory.is_player() { continue }
-            if factory.production == 0 { continue }
-
-            let mut turn = -1;
-            let mut state = self.clone();
-            while !state.factories.get(&id).unwrap().is_enemy() && turn < 20 {
-                //print_err!("turn : {} id{} owner{}", turn, state.factories.get(&id).unwrap().id, state.factories.get(&id).unwrap().owner);
-                state.sim_next_turn();
-                turn += 1;
+    pub fn defend_strategy(&mut self) {
+
+        // Keep the garrison the worst case enemy attack requires, launched or not
+        let threats = self.compute_threats(self.params.threat_horizon);
+        for (id, threat) in threats.iter() {
+            let factory = self.factories.get_mut(id).unwrap();
+            let spare = (factory.cyborg_count - threat.required_garrison).max(0);
+            if spare < factory.cyborg_remaining {
+                trace!(Category::Defend, Level::Info, "hold", "factory" => *id, "garrison" => factory.cyborg_count, "required" => threat.required_garrison,
+                       "deadline" => threat.deadline, "reason" => "worst case enemy arrivals");
+                factory.cyborg_remaining = spare;
             }
-            if turn < 20 {
-                let captured_fac = state.factories.get(&id).unwrap();
-                let mut need_cyborg = captured_fac.cyborg_count - turn * captured_fac.production;
-                print_err!("[DEFEND] factory {} will captured in {} turns. Defend {}", id, turn, need_cyborg);
-
-                if need_cyborg < 0 { need_cyborg *= -1 }
-
-                for &(distance, id2) in factory.distances.iter() {
-                    //if distance > turn { break }
-
-                    let mut factory_renfort = self.factories.get(&id2).unwrap();
-                    if !factory_renfort.is_player() { continue }
-                    //if factory_renfort.cyborg_remaining < need_cyborg { continue }
-
-                    self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory_renfort.id, factory_end: factory.id, cyborg_count: need_cyborg, turn_remaining: distance});
-                }
-
-            }
-        }
-        for troop in self.troop_commands.iter() {
-            let mut factory_renfort = self.factories.get_mut(&troop.factory_start).unwrap();
-            factory_renfort.cyborg_remaining -= troop.cyborg_count;
         }
 
-
-
-         // Check if too close enemy potential attack
-        /*
-        let factories_immu = self.factories.clone();
-        for (id, factory) in self.factories.iter_mut() {
-            if !factory.is_player() { continue }
-
-            let mut sum_cyborg_potential = 0;
-            for &(distance, id2) in factory.distances.iter() {
-                if distance > 2 { break }
-                let enemy_factory = factories_immu.get(&id2).unwrap();
-                if enemy_factory.is_enemy() {
-                    sum_cyborg_potential += enemy_factory.cyborg_count;
-                }
-            }
-
-            if sum_cyborg_potential >= factory.cyborg_count {
-                print_err!("Need defense potential attack id:{}", factory.id);
-                factory.cyborg_remaining = 0;
-            } else {
-                factory.cyborg_remaining -= sum_cyborg_potential;
-            }
-
-        }
-        */
+        self.reinforce();
+        self.counter_attack();
 
         self.compute_inc();
 
-        if self.troop_commands.len() == 0 {
+        if self.troop_commands.is_empty() {
             self.neutral_first_strategy();
         }
+        self.allocate_moves();
 
+        self.supply_frontline();
 
     }
 
-    fn swarm_strategy(&mut self) {
-        for (id, factory) in self.factories.iter() {
+    pub fn swarm_strategy(&mut self) {
+        for factory in self.factories.values() {
             if factory.is_player() && factory.cyborg_count > self.factories.len() as i32 {
-                let mut id1 = factory.id;
-                let mut cyborg_count = factory.cyborg_count;
+                let id1 = factory.id;
 
-                for (id, factory2) in self.factories.iter() {
+                for factory2 in self.factories.values() {
                     if !factory2.is_player() {
-                        let mut id2 = factory2.id;
+                        let id2 = factory2.id;
                         self.commands.push(format!("MOVE {} {} {}", id1, id2, 1));
                     }
                 }
@@ -465,12 +2331,12 @@ impl GameState {
         }
     }
 
-    fn compute_bomb(&mut self) {
+    pub fn compute_bomb(&mut self) {
         if self.bomb_count == 0 { return }
 
         // Get the max prod
         let mut factory_prod = 1;
-        for (id, factory) in self.factories.iter() {
+        for factory in self.factories.values() {
             if factory.is_enemy() && factory.production > factory_prod {
                 factory_prod = factory.production;
                 break;
@@ -478,8 +2344,8 @@ impl GameState {
         }
 
         // Get the target
-        let mut aimed_factory: &Factory = &Factory{id: -99, owner: -99, cyborg_count: -99, production: -99, distances: Vec::new(), cyborg_count_combat: 0, cyborg_remaining: 0};
-        for (id, factory) in self.factories.iter() {
+        let mut aimed_factory: &Factory = &Factory{id: -99, owner: -99, cyborg_count: -99, production: -99, distances: Vec::new(), disrupted: 0, cyborg_remaining: 0};
+        for factory in self.factories.values() {
             if factory.is_enemy() && factory.cyborg_count > aimed_factory.cyborg_count && factory.production == factory_prod && self.bomb_last != factory.id {
                 aimed_factory = factory;
             }
@@ -488,234 +2354,707 @@ impl GameState {
         if aimed_factory.id < 0 { return }
 
         // Get the source (the closest)
-        for &(distance, id2) in aimed_factory.distances.iter() {
+        for &(_, id2) in aimed_factory.distances.iter() {
             let factory2 = self.factories.get(&id2).unwrap();
             if factory2.is_player() {
                 self.bomb_count -= 1;
                 self.bomb_last = aimed_factory.id;
                 self.commands.push(format!("BOMB {} {}", id2, aimed_factory.id));
+                trace!(Category::Bomb, Level::Info, "bomb", "from" => id2, "to" => aimed_factory.id, "production" => aimed_factory.production,
+                       "garrison" => aimed_factory.cyborg_count, "bombs_left" => self.bomb_count);
                 return;
             }
         }
 
     }
 
-    fn print_factories(&mut self) {
-        for (id, factory) in self.factories.iter() {
-            print_err!("{} {} {} {}", factory.id, factory.owner, factory.cyborg_count, factory.production);
+    pub fn print_factories(&mut self) {
+        for factory in self.factories.values() {
+            trace!(Category::Turn, Level::Debug, "factory", "id" => factory.id, "owner" => factory.owner,
+                   "count" => factory.cyborg_count, "production" => factory.production);
         }
     }
 
-    fn print_commands(&mut self) {
+    pub fn random_search(&mut self) -> GameState {
+        let mut max_game = self.clone();
+        let mut max_score = self.evaluate();
+        let mut iterations = 0;
+        for _ in 0..1000 {
+            iterations += 1;
+            let mut game_cloned = self.clone();
+            game_cloned.rng = self.rng.fork();
+            game_cloned.random_strategy();
+            game_cloned.random_strategy();
 
-        let mut final_command = "MSG El Psy Congroo".to_string();
-        for command in self.commands.iter() {
-            final_command.push_str(";");
-            final_command.push_str(&command);
-        }
+            let mut game_cloned_cloned = game_cloned.clone();
+            game_cloned_cloned.sim_next_turn();
+            let score = game_cloned_cloned.evaluate();
+            if score > max_score {
+                max_score = score;
+                max_game = game_cloned;
+            }
 
-        for troop in self.troop_commands.iter() {
-            final_command.push_str(";");
-            final_command.push_str(&format!("MOVE {} {} {}", troop.factory_start, troop.factory_end, troop.cyborg_count));
+            if self.budget.should_stop() { break }
         }
 
-        println!("{}", final_command);
-
-        self.troop_commands.clear();
-        self.commands.clear();
+        trace!(Category::Search, Level::Info, "random_search", "iterations" => iterations, "score" => max_score);
+        return max_game;
     }
+}
+}
+pub mod selfplay {
+use std::collections::HashMap;
+use std::fs;
+use std::thread;
+use std::sync::{mpsc, Arc, Mutex};
 
-    fn evaluate(&mut self) -> i32 {
-        let mut score: i32 = 0;
+use crate::model::{GameState, HasOwner, Troop};
+use crate::params::Params;
+use crate::rng::Rng;
+use crate::strategy::Brain;
+use crate::time::TimeBudget;
 
-        // Cyborg in factories
-        for (id, factory) in self.factories.iter() {
-            if factory.is_player() {
-                score += factory.cyborg_count;
-                score += factory.production * 10;
-            } else if factory.is_enemy() {
-                score -= factory.cyborg_count;
-                score -= factory.production * 10;
-            }
+impl GameState {
+    // Load the referee state of a local game as seen by `side`
+    pub fn observe(&mut self, referee: &GameState, side: i32) {
+        self.troops.clear();
+        self.bombs.clear();
+        self.bombing = false;
 
+        for (id, factory) in self.factories.iter_mut() {
+            let real = referee.factories.get(id).unwrap();
+            factory.owner = real.owner * side;
+            factory.cyborg_count = real.cyborg_count;
+            factory.production = real.production;
+            factory.disrupted = real.disrupted;
+            factory.cyborg_remaining = factory.cyborg_count;
         }
 
-        // Cyborg in troops
-        for troop in self.troops.iter() {
-            if troop.is_player() {
-                score += troop.cyborg_count;
-            } else if troop.is_enemy() {
-                score -= troop.cyborg_count;
+        for troop in referee.troops.iter() {
+            let mut troop = troop.clone();
+            troop.owner *= side;
+            self.troops.push_back(troop);
+        }
+
+        self.next_entity_id = referee.next_entity_id;
+        for bomb in referee.bombs.iter() {
+            let mut bomb = bomb.clone();
+            bomb.owner *= side;
+            if bomb.is_enemy() {
+                self.bombing = true;
+                bomb.factory_end = -1;
+                bomb.turn_remaining = -1;
             }
+            self.bombs.push(bomb);
+        }
+
+        self.budget = TimeBudget::start(self.nb_turn, self.params.turn_budget_ms);
+    }
+}
+
+// Approximation of the referee map generator: factory 0 in the middle, the others mirrored by pair
+pub fn generate_map(seed: u64) -> GameState {
+    let mut rng = Rng::new(seed);
+    let (width, height) = (16000, 6500);
+    let factory_count = 7 + 2 * rng.gen_range(0, 5);
+
+    let mut positions: Vec<(i32, i32)> = vec![(width / 2, height / 2)];
+    while positions.len() < factory_count as usize {
+        let (x, y) = (rng.gen_range(700, width - 700), rng.gen_range(700, height - 700));
+        let mirror = (width - x, height - y);
+        let far_enough = positions.iter().chain([mirror].iter())
+            .all(|&(x2, y2)| (((x - x2).pow(2) + (y - y2).pow(2)) as f32).sqrt() > 1900.0);
+        if far_enough {
+            positions.push((x, y));
+            positions.push(mirror);
+        }
+    }
 
+    let mut map = GameState::new();
+    for i in 0..factory_count {
+        for j in (i + 1)..factory_count {
+            let (x1, y1) = positions[i as usize];
+            let (x2, y2) = positions[j as usize];
+            let length = (((x1 - x2).pow(2) + (y1 - y2).pow(2)) as f32).sqrt();
+            map.factory_distance.insert((i, j), (((length - 1400.0) / 800.0).ceil() as i32).max(1));
         }
+    }
+    map.init_factories_distance(factory_count);
 
-        // Factories that will be catpured
-        for (id, factory) in self.factories.iter() {
-            let mut cyborg_count: i32 = factory.cyborg_count * factory.owner;
-            for troop in self.troops.iter() {
-                if factory.id == troop.factory_end {
-                    cyborg_count += troop.cyborg_count * troop.owner;
-                }
+    for i in 0..factory_count {
+        let factory = map.factories.get_mut(&i).unwrap();
+        factory.owner = 0;
+        factory.production = if i == 0 { 0 } else { rng.gen_range(0, 4) };
+        factory.cyborg_count = if i == 0 { 0 } else { rng.gen_range(0, 6 * factory.production + 1) };
+    }
+    // Second factory of each pair is a copy of the first one
+    for i in (2..factory_count).step_by(2) {
+        let first = map.factories.get(&(i - 1)).unwrap().clone();
+        let second = map.factories.get_mut(&i).unwrap();
+        second.production = first.production;
+        second.cyborg_count = first.cyborg_count;
+    }
 
-            }
+    let start_cyborgs = rng.gen_range(15, 31);
+    map.rng = rng;
+    for &(id, owner) in [(1, 1), (2, -1)].iter() {
+        let factory = map.factories.get_mut(&id).unwrap();
+        factory.owner = owner;
+        factory.cyborg_count = start_cyborgs;
+        factory.production = 1.max(factory.production);
+    }
+
+    return map;
+}
 
-            if cyborg_count > 0 {
-                score += factory.production * 10;
-            } else if cyborg_count < 0 {
-                score -= factory.production * 10;
+// A named AI of local games
+#[derive(Clone)]
+pub struct Variant {
+    pub name: String,
+    pub brain: Brain,
+    pub params: Params
+}
+
+impl Variant {
+    // "name=rules", "name=random" or "name=rules:params.txt"
+    pub fn parse(spec: &str) -> Option<Variant> {
+        let mut parts = spec.splitn(2, '=');
+        let name = parts.next().unwrap().to_string();
+        let mut parts = parts.next()?.splitn(2, ':');
+        let brain = match parts.next()? {
+            "rules" => Brain::Rules,
+            "random" => Brain::RandomSearch,
+            _ => return None
+        };
+
+        let mut params = Params::load();
+        if let Some(path) = parts.next() {
+            match fs::read_to_string(path) {
+                Ok(config) => params.parse(&config),
+                Err(err) => { print_err!("Cannot read {}: {}", path, err); return None }
             }
+        }
 
+        return Some(Variant{name: name, brain: brain, params: params});
+    }
+}
+
+pub fn new_bot(map: &GameState, params: &Params, side: i32) -> GameState {
+    let mut bot = GameState::new();
+    bot.rng = Rng::new(map.rng.clone().next_u64() ^ side as u64);
+    bot.params = *params;
+    bot.bomb_count = params.bomb_count;
+    bot.factory_distance = map.factory_distance.clone();
+    bot.init_factories_distance(map.factories.len() as i32);
+    return bot;
+}
+
+// Hand the bot orders to the referee, like the real referee a troop can't take more than the source garrison
+pub fn issue_orders(referee: &mut GameState, bot: &mut GameState, side: i32) {
+    let mut garrisons: HashMap<i32, i32> = referee.factories.values()
+        .filter(|fac| fac.owner == side)
+        .map(|fac| (fac.id, fac.cyborg_count))
+        .collect();
+
+    for troop in bot.troop_commands.iter() {
+        if troop.factory_start == troop.factory_end { continue }
+        let available = match garrisons.get_mut(&troop.factory_start) {
+            Some(available) => available,
+            None => continue
+        };
+
+        let cyborg_count = troop.cyborg_count.min(*available);
+        if cyborg_count <= 0 { continue }
+        *available -= cyborg_count;
+
+        let distance = referee.distance(troop.factory_start, troop.factory_end);
+        referee.troop_commands.push_back(Troop{id: 999, owner: side, factory_start: troop.factory_start, factory_end: troop.factory_end, cyborg_count: cyborg_count, turn_remaining: distance});
+    }
+
+    // INC and BOMB belong to the owner of their source factory
+    for command in bot.commands.iter() {
+        let source = command.split_whitespace().nth(1).and_then(|id| id.parse::<i32>().ok());
+        if let Some(id) = source {
+            if garrisons.contains_key(&id) { referee.commands.push(command.clone()) }
         }
+    }
 
-        //print_err!("Score : {}", score);
-        return score;
+    bot.troop_commands.clear();
+    bot.commands.clear();
+}
+
+// Winner of a local game: 1, -1 or 0 for a draw
+pub fn play_game(map: &GameState, player: &Variant, enemy: &Variant) -> i32 {
+    let mut referee = map.clone();
+    let mut bots = [(new_bot(map, &player.params, 1), player.brain, 1), (new_bot(map, &enemy.params, -1), enemy.brain, -1)];
+
+    while !referee.is_terminal() {
+        for &mut (ref mut bot, brain, side) in bots.iter_mut() {
+            bot.observe(&referee, side);
+            bot.play_turn(brain);
+            issue_orders(&mut referee, bot, side);
+            bot.nb_turn += 1;
+        }
+        referee.sim_next_turn();
     }
 
-    fn test(&mut self) {
-        let mut fac = self.clone();
+    return referee.winner();
+}
+
+pub struct MatchResult {
+    pub wins: i32,
+    pub draws: i32,
+    pub losses: i32
+}
 
-        fac.commands = Vec::new();
+impl MatchResult {
+    pub fn add(&mut self, winner: i32) {
+        match winner {
+            1 => self.wins += 1,
+            -1 => self.losses += 1,
+            _ => self.draws += 1
+        }
+    }
 
+    pub fn games(&self) -> i32 {
+        return self.wins + self.draws + self.losses;
     }
 
-    fn sim_next_turn(&mut self) {
-        // Troops Moving
-        for troop in self.troops.iter_mut() {
-            troop.turn_remaining -= 1;
-            if troop.turn_remaining == 0 {
-                let mut factory = self.factories.get_mut(&troop.factory_end).unwrap();
+    // Draws count as half a win
+    pub fn win_rate(&self) -> f32 {
+        return (self.wins as f32 + self.draws as f32 * 0.5) / self.games().max(1) as f32;
+    }
 
-                if factory.owner == troop.owner {
-                    factory.cyborg_count += troop.cyborg_count
-                } else {
-                    factory.cyborg_count_combat += troop.cyborg_count * troop.owner;
-                }
+    // Half width of the 95% confidence interval (normal approximation)
+    pub fn confidence(&self) -> f32 {
+        let p = self.win_rate();
+        return 1.96 * (p * (1.0 - p) / self.games().max(1) as f32).sqrt();
+    }
+}
 
-            }
+// Each map is played twice, one time per seat
+pub fn play_match(player: &Variant, enemy: &Variant, maps: i32, seed: u64) -> MatchResult {
+    let mut result = MatchResult{wins: 0, draws: 0, losses: 0};
+    for i in 0..maps {
+        let map = generate_map(seed + i as u64);
+        for &winner in [play_game(&map, player, enemy), -play_game(&map, enemy, player)].iter() {
+            result.add(winner);
         }
-        self.troops = self.troops.iter()
-            .filter(|troop| troop.turn_remaining > 0)
-            .map(|troop| troop.clone())
-            .collect();
+    }
+    return result;
+}
 
-        // Orders Execution
-        for troop in self.troop_commands.iter_mut() {
-            let mut factory = self.factories.get_mut(&troop.factory_start).unwrap();
-            factory.cyborg_count -= troop.cyborg_count;
+// Hill climbing by self-play: `cargo run --release -p bot -- tune [iterations] [maps per match] [output file]`
+pub fn tune(args: &[String], seed: u64) {
+    let iterations = args.first().map_or(50, |arg| parse_input!(arg, i32));
+    let maps = args.get(1).map_or(10, |arg| parse_input!(arg, i32));
+    let output = args.get(2).map_or("params.txt".to_string(), |arg| arg.clone());
+
+    let mut rng = Rng::new(seed);
+    let mut best = Params::load();
+    for iteration in 0..iterations {
+        let (candidate, key) = best.mutate(&mut rng);
+        let result = play_match(&Variant{name: "candidate".to_string(), brain: Brain::Rules, params: candidate},
+                                &Variant{name: "best".to_string(), brain: Brain::Rules, params: best},
+                                maps, iteration as u64 * maps as u64);
+        let value = candidate.entries().iter().find(|entry| entry.0 == key).unwrap().1;
+        println!("#{} {}={} win rate {:.1}% +/- {:.1}% ({}W {}D {}L)", iteration, key, value,
+                 result.win_rate() * 100.0, result.confidence() * 100.0, result.wins, result.draws, result.losses);
+
+        if result.win_rate() > 0.5 {
+            best = candidate;
+            match fs::write(&output, best.to_config()) {
+                Ok(_) => println!("New best written to {}", output),
+                Err(err) => print_err!("Cannot write {}: {}", output, err)
+            }
         }
+    }
 
-        /*
-        for troop in self.troop_commands.iter_mut() {
-            self.troops.push_back(troop.clone());
+    println!("{}", best.to_config());
+}
+
+// Outcome of the two games of a map between variants first and second, from the first one point of view
+struct Pairing {
+    first: usize,
+    second: usize,
+    map: i32,
+    winners: [i32; 2]
+}
+
+// Round robin on seeded maps in both seats: `cargo run --release -p bot -- tournament [maps] [threads] [name=rules|random[:params file]]...`
+pub fn tournament(args: &[String]) {
+    let maps = args.first().map_or(10, |arg| parse_input!(arg, i32));
+    let threads = args.get(1).map_or(thread::available_parallelism().map_or(1, |count| count.get()), |arg| parse_input!(arg, usize));
+    let mut variants: Vec<Variant> = args.iter().skip(2).filter_map(|spec| {
+        let variant = Variant::parse(spec);
+        if variant.is_none() { print_err!("Invalid variant {}", spec) }
+        variant
+    }).collect();
+    if variants.is_empty() {
+        variants = vec![Variant::parse("rules=rules").unwrap(), Variant::parse("random=random").unwrap()];
+    }
+
+    let mut jobs = Vec::new();
+    for first in 0..variants.len() {
+        for second in (first + 1)..variants.len() {
+            for map in 0..maps {
+                jobs.push((first, second, map));
+            }
         }
-        */
-        //self.troops.extend(self.troop_commands.iter());
-        self.troops.append(&mut self.troop_commands);
+    }
 
-        // Production & Combat
-        for (id, factory) in self.factories.iter_mut() {
-            // Production
-            if !factory.is_neutral() {
-                factory.cyborg_count += factory.production;
-            }
-
-            // Combat
-            if factory.cyborg_count_combat != 0 {
-                if factory.is_neutral() {
-                    if factory.cyborg_count_combat < 0 {
-                        factory.cyborg_count += factory.cyborg_count_combat;
-                        if factory.cyborg_count < 0 {
-                            factory.owner = -1;
-                            factory.cyborg_count *= -1;
-                        }
-                    } else if factory.cyborg_count_combat > 0 {
-                        factory.cyborg_count -= factory.cyborg_count_combat;
-                        if factory.cyborg_count < 0 {
-                            factory.owner = 1;
-                            factory.cyborg_count *= -1;
-                        }
-                    }
-                } else {
-                    factory.cyborg_count = factory.cyborg_count * factory.owner + factory.cyborg_count_combat;
-                    if factory.cyborg_count < 0 {
-                        factory.cyborg_count *= -1;
-                        factory.owner = -1;
-                    } else if factory.cyborg_count > 0 {
-                        factory.owner = 1;
-                    }
+    let variants = Arc::new(variants);
+    let jobs = Arc::new(Mutex::new(jobs));
+    let (sender, receiver) = mpsc::channel();
+    let mut workers = Vec::new();
+    for _ in 0..threads.max(1) {
+        let (variants, jobs, sender) = (variants.clone(), jobs.clone(), sender.clone());
+        workers.push(thread::spawn(move || {
+            loop {
+                let job = jobs.lock().unwrap().pop();
+                let (first, second, map) = match job { Some(job) => job, None => break };
+                let game_map = generate_map(map as u64);
+                let winners = [play_game(&game_map, &variants[first], &variants[second]),
+                               -play_game(&game_map, &variants[second], &variants[first])];
+                sender.send(Pairing{first: first, second: second, map: map, winners: winners}).unwrap();
+            }
+        }));
+    }
+    drop(sender);
 
-                    //print_err!("Captured id:{} owner:{} count:{}", factory.id, factory.owner, factory.cyborg_count);
+    let mut pairings: Vec<Pairing> = receiver.iter().collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    pairings.sort_by_key(|pairing| (pairing.map, pairing.first, pairing.second));
+
+    // Elo updated game after game in a fixed order
+    let mut results: Vec<MatchResult> = variants.iter().map(|_| MatchResult{wins: 0, draws: 0, losses: 0}).collect();
+    let mut elo: Vec<f32> = vec![1500.0; variants.len()];
+    let mut map_points: Vec<Vec<f32>> = vec![vec![0.0; variants.len()]; maps as usize];
+    for pairing in pairings.iter() {
+        for &winner in pairing.winners.iter() {
+            results[pairing.first].add(winner);
+            results[pairing.second].add(-winner);
+
+            let score = (winner as f32 + 1.0) / 2.0;
+            let expected = 1.0 / (1.0 + 10f32.powf((elo[pairing.second] - elo[pairing.first]) / 400.0));
+            elo[pairing.first] += 16.0 * (score - expected);
+            elo[pairing.second] -= 16.0 * (score - expected);
+
+            map_points[pairing.map as usize][pairing.first] += score;
+            map_points[pairing.map as usize][pairing.second] += 1.0 - score;
+        }
+    }
 
-                }
+    println!("{:<16} {:>5} {:>5} {:>5} {:>7}", "Variant", "W", "D", "L", "Elo");
+    for (i, variant) in variants.iter().enumerate() {
+        println!("{:<16} {:>5} {:>5} {:>5} {:>7.0}", variant.name, results[i].wins, results[i].draws, results[i].losses, elo[i]);
+    }
 
-                factory.cyborg_count_combat = 0;
+    println!();
+    for first in 0..variants.len() {
+        for second in (first + 1)..variants.len() {
+            let mut result = MatchResult{wins: 0, draws: 0, losses: 0};
+            for pairing in pairings.iter().filter(|pairing| pairing.first == first && pairing.second == second) {
+                result.add(pairing.winners[0]);
+                result.add(pairing.winners[1]);
             }
+            println!("{} vs {}: {}W {}D {}L ({:.1}% +/- {:.1}%)", variants[first].name, variants[second].name,
+                     result.wins, result.draws, result.losses, result.win_rate() * 100.0, result.confidence() * 100.0);
         }
+    }
 
-        // Bombs
+    println!();
+    for (map, points) in map_points.iter().enumerate() {
+        let line: Vec<String> = variants.iter().enumerate().map(|(i, variant)| format!("{} {}", variant.name, points[i])).collect();
+        println!("Map {} ({} factories): {}", map, generate_map(map as u64).factories.len(), line.join(", "));
+    }
+}
+}
+pub mod replay {
+use std::collections::VecDeque;
+use std::fs;
+
+use crate::model::GameState;
+use crate::params::Params;
+use crate::protocol::Input;
+use crate::rng::Rng;
+use crate::strategy::Brain;
+
+// Feed a GITC_REPLAY recording back to the rule based AI and diff its commands: `cargo run --release -p bot -- replay <file>`
+pub fn replay(args: &[String], seed: Option<u64>) {
+    let path = match args.first() {
+        Some(path) => path,
+        None => { print_err!("Usage: cargo run --release -p bot -- replay <file>"); return }
+    };
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => { print_err!("Cannot read {}: {}", path, err); return }
+    };
+
+    let mut params = Params::new();
+    let mut recorded_seed = 0;
+    let mut inputs = VecDeque::new();
+    let mut outputs: Vec<(String, String)> = Vec::new(); // (commands, elapsed ms)
+    for line in content.lines() {
+        let (tag, value) = line.split_at(line.find(' ').unwrap_or(line.len()));
+        let value = value.trim_start_matches(' ').to_string();
+        match tag {
+            "P" => params.parse(&value),
+            "S" => recorded_seed = parse_input!(value, u64),
+            "I" => inputs.push_back(value),
+            "O" => outputs.push((value, "?".to_string())),
+            "T" => if let Some(output) = outputs.last_mut() { output.1 = value },
+            _ => print_err!("Unknown replay line {}", line)
+        }
+    }
 
+    let mut input = Input::replay(inputs);
+    let mut game_state: GameState = GameState::new();
+    game_state.rng = Rng::new(seed.unwrap_or(recorded_seed));
+    game_state.params = params;
+    game_state.bomb_count = params.bomb_count;
+    game_state.init_links(&mut input);
+
+    let mut mismatches = 0;
+    for (turn, (recorded, elapsed)) in outputs.iter().enumerate() {
+        game_state.init_entities(&mut input);
+        game_state.play_turn(Brain::Rules);
+        let output = game_state.take_commands();
+        game_state.nb_turn += 1;
 
+        if output == *recorded { continue }
+        mismatches += 1;
 
+        let recorded_commands: Vec<&str> = recorded.split(';').collect();
+        let output_commands: Vec<&str> = output.split(';').collect();
+        println!("Turn {} (recorded in {} ms)", turn, elapsed);
+        for command in recorded_commands.iter().filter(|command| !output_commands.contains(command)) {
+            println!("- {}", command);
+        }
+        for command in output_commands.iter().filter(|command| !recorded_commands.contains(command)) {
+            println!("+ {}", command);
+        }
+        if recorded_commands.len() == output_commands.len() && recorded_commands.iter().all(|command| output_commands.contains(command)) {
+            println!("  Same commands in another order");
+        }
     }
 
+    println!("{} turns replayed, {} different", outputs.len(), mismatches);
 }
+}
+pub mod golden {
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fs;
 
+use crate::model::{Bomb, Factory, GameState, HasOwner, Troop};
+use crate::protocol::Input;
 
-fn rnd_range(start: Instant, max: i32) -> i32 {
-    return (start.elapsed().subsec_nanos() % max as u32) as i32;
+// One turn of a golden game: the entities sent to player 1 and the commands of both players
+struct GoldenTurn {
+    entities: Vec<String>,
+    commands: Vec<(i32, String)>
 }
 
-fn main() {
-    let mut game_state: GameState = GameState::new();
+// Recorded game, see golden/: "LINK a b distance" lines, then for each turn "TURN", "E <entity line>" and "CMD <1|-1> <command>"
+fn parse_golden_game(text: &str) -> (Vec<(i32, i32, i32)>, Vec<GoldenTurn>) {
+    let mut links = Vec::new();
+    let mut turns: Vec<GoldenTurn> = Vec::new();
+    for line in text.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') { continue }
+        let (tag, value) = line.split_at(line.find(' ').unwrap_or(line.len()));
+        let value = value.trim();
+        match tag {
+            "LINK" => {
+                let ids: Vec<i32> = value.split_whitespace().map(|id| parse_input!(id, i32)).collect();
+                links.push((ids[0], ids[1], ids[2]));
+            },
+            "TURN" => turns.push(GoldenTurn{entities: Vec::new(), commands: Vec::new()}),
+            "E" => turns.last_mut().expect("Entity before the first TURN").entities.push(value.to_string()),
+            "CMD" => {
+                let (side, command) = value.split_at(value.find(' ').unwrap_or(value.len()));
+                turns.last_mut().expect("Command before the first TURN").commands.push((parse_input!(side, i32), command.trim().to_string()));
+            },
+            _ => panic!("Unknown golden game line {}", line)
+        }
+    }
+    return (links, turns);
+}
 
-    let mut input_line = String::new();
-    io::stdin().read_line(&mut input_line).unwrap();
-    let factory_count = parse_input!(input_line, i32); // the number of factories
-    let mut input_line = String::new();
-    io::stdin().read_line(&mut input_line).unwrap();
-    let link_count = parse_input!(input_line, i32); // the number of links between factories
-    for i in 0..link_count as usize {
-        let mut input_line = String::new();
-        io::stdin().read_line(&mut input_line).unwrap();
-        let inputs = input_line.split(" ").collect::<Vec<_>>();
-        let factory_1 = parse_input!(inputs[0], i32);
-        let factory_2 = parse_input!(inputs[1], i32);
-        let distance = parse_input!(inputs[2], i32);
+fn troop_summary(troop: &Troop) -> (i32, i32, i32, i32, i32) {
+    return (troop.owner, troop.factory_start, troop.factory_end, troop.cyborg_count, troop.turn_remaining);
+}
 
-        print_err!("Id1:{} Id2:{} Distance:{}", factory_1, factory_2, distance);
-        game_state.factory_distance.insert((factory_1, factory_2), distance);
+// The target of enemy bombs is hidden, like in the referee input
+fn bomb_summary(bomb: &Bomb) -> (i32, i32, i32, i32) {
+    if bomb.is_enemy() {
+        return (bomb.owner, bomb.factory_start, -1, -1);
     }
-    game_state.init_factories_distance(factory_count);
+    return (bomb.owner, bomb.factory_start, bomb.factory_end, bomb.turn_remaining);
+}
 
+// Elements of `expected` missing from `simulated` and the other way around
+pub fn diff_entities<T: PartialEq + Clone>(expected: &[T], simulated: &[T]) -> (Vec<T>, Vec<T>) {
+    let mut unexpected = simulated.to_vec();
+    let mut missing = Vec::new();
+    for entity in expected.iter() {
+        match unexpected.iter().position(|other| other == entity) {
+            Some(position) => { unexpected.remove(position); },
+            None => missing.push(entity.clone())
+        }
+    }
+    return (missing, unexpected);
+}
 
-    // game loop
-    loop {
-        let start = Instant::now();
+// Replay sim_next_turn from each recorded turn with the recorded commands and report every difference with the next turn
+pub fn check_golden_game(text: &str) -> Vec<String> {
+    let (links, turns) = parse_golden_game(text);
+    let mut base = GameState::new();
+    for &(id1, id2, distance) in links.iter() {
+        base.factory_distance.insert((id1, id2), distance);
+    }
+    let factory_count = links.iter().map(|&(id1, id2, _)| id1.max(id2)).max().unwrap_or(-1) + 1;
+    base.init_factories_distance(factory_count);
+
+    let load = |turn: &GoldenTurn| {
+        let mut lines: VecDeque<String> = turn.entities.iter().cloned().collect();
+        lines.push_front(turn.entities.len().to_string());
+        let mut state = base.clone();
+        state.init_entities(&mut Input::replay(lines));
+        state
+    };
+
+    let mut reports = Vec::new();
+    let mut enemy_bombs: HashMap<i32, (i32, i32)> = HashMap::new(); // id -> (target, turns) learned from the enemy BOMB commands
+    for (t, turn) in turns.iter().enumerate().take(turns.len().saturating_sub(1)) {
+        let mut state = load(turn);
+        state.nb_turn = t as i32;
+        for bomb in state.bombs.iter_mut().filter(|bomb| bomb.is_enemy()) {
+            if let Some(&(target, turn_remaining)) = enemy_bombs.get(&bomb.id) {
+                bomb.factory_end = target;
+                bomb.turn_remaining = turn_remaining;
+            }
+        }
+        for &(side, ref command) in turn.commands.iter() {
+            state.push_order(side, command);
+        }
+        let known_bombs: Vec<i32> = state.bombs.iter().map(|bomb| bomb.id).collect();
+        state.sim_next_turn();
 
-        game_state.init_entities();
+        let expected = load(&turns[t + 1]);
+        let prefix = format!("turn {} -> {}:", t, t + 1);
 
-        /*
-        let mut max_game = game_state.clone();
-        let mut max_score = game_state.evaluate();
-        for i in 0..1000 {
-            let mut game_cloned = game_state.clone();
-            game_cloned.random_strategy();
-            game_cloned.random_strategy();
+        for (id, factory) in expected.factories.iter() {
+            let simulated = state.factories.get(id).unwrap();
+            let summary = |fac: &Factory| format!("owner {} cyborgs {} production {} disrupted {}", fac.owner, fac.cyborg_count, fac.production, fac.disrupted);
+            if summary(factory) != summary(simulated) {
+                reports.push(format!("{} factory {} expected {}, simulated {}", prefix, id, summary(factory), summary(simulated)));
+            }
+        }
 
-            let mut game_cloned_cloned = game_cloned.clone();
-            game_cloned_cloned.sim_next_turn();
-            let mut score = game_cloned_cloned.evaluate();
-            if score > max_score {
-                max_score = score;
-                max_game = game_cloned;
+        let expected_troops: Vec<_> = expected.troops.iter().map(troop_summary).collect();
+        let simulated_troops: Vec<_> = state.troops.iter().map(troop_summary).collect();
+        let (missing, unexpected) = diff_entities(&expected_troops, &simulated_troops);
+        for &(label, troops) in [("missing", &missing), ("unexpected", &unexpected)].iter() {
+            for &(owner, start, end, count, turn_remaining) in troops.iter() {
+                reports.push(format!("{} {} troop owner {} {}->{} cyborgs {} turns {}", prefix, label, owner, start, end, count, turn_remaining));
+            }
+        }
+
+        // Learn where the new enemy bombs go, the expected ids are matched by source factory
+        for bomb in state.bombs.iter().filter(|bomb| bomb.is_enemy()) {
+            let id = if known_bombs.contains(&bomb.id) { Some(bomb.id) } else {
+                expected.bombs.iter()
+                    .find(|other| other.is_enemy() && other.factory_start == bomb.factory_start && !enemy_bombs.contains_key(&other.id))
+                    .map(|other| other.id)
+            };
+            if let Some(id) = id {
+                enemy_bombs.insert(id, (bomb.factory_end, bomb.turn_remaining));
+            }
+        }
+
+        let expected_bombs: Vec<_> = expected.bombs.iter().map(bomb_summary).collect();
+        let simulated_bombs: Vec<_> = state.bombs.iter().map(bomb_summary).collect();
+        let (missing, unexpected) = diff_entities(&expected_bombs, &simulated_bombs);
+        for &(label, bombs) in [("missing", &missing), ("unexpected", &unexpected)].iter() {
+            for &(owner, start, end, turn_remaining) in bombs.iter() {
+                reports.push(format!("{} {} bomb owner {} {}->{} turns {}", prefix, label, owner, start, end, turn_remaining));
             }
+        }
+    }
+
+    return reports;
+}
 
-            let elapsed = start.elapsed();
-            if elapsed.subsec_nanos() / 1_000_000 > 49 { break }
+// Check recorded games against the simulator: `cargo run --release -p bot -- golden <file>...`
+pub fn golden(args: &[String]) {
+    for path in args.iter() {
+        let reports = match fs::read_to_string(path) {
+            Ok(text) => check_golden_game(&text),
+            Err(err) => { print_err!("Cannot read {}: {}", path, err); continue }
+        };
+
+        println!("{}: {}", path, if reports.is_empty() { "ok".to_string() } else { format!("{} differences", reports.len()) });
+        for report in reports.iter() {
+            println!("  {}", report);
         }
+    }
+}
+
+}
 
+use std::env;
+
+use crate::model::GameState;
+use crate::params::Params;
+use crate::protocol::Input;
+use crate::rng::{startup_seed, Rng};
+use crate::strategy::Brain;
+use crate::trace::{Category, Level};
+
+// Contest bot reading the referee on stdin, or one of the local modes: replay, tune, tournament, golden
+pub fn run(mut args: Vec<String>) {
+    let has_seed = args.iter().any(|arg| arg == "--seed") || env::var("GITC_SEED").is_ok();
+    if args.len() > 1 && args[1] == "replay" {
+        // The recorded seed unless one is given
+        let seed = if has_seed { Some(startup_seed(&mut args)) } else { None };
+        replay::replay(&args[2..], seed);
+        return;
+    }
+
+    let seed = startup_seed(&mut args);
+    if args.len() > 1 && args[1] == "tune" {
+        selfplay::tune(&args[2..], seed);
+        return;
+    }
+    if args.len() > 1 && args[1] == "tournament" {
+        selfplay::tournament(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "golden" {
+        golden::golden(&args[2..]);
+        return;
+    }
+
+    let mut game_state: GameState = GameState::new();
+    game_state.rng = Rng::new(seed);
+    game_state.params = Params::load();
+    game_state.bomb_count = game_state.params.bomb_count;
+
+    let mut input = Input::stdin();
+    input.record('P', &game_state.params.to_config().trim().replace('\n', ","));
+    input.record('S', &seed.to_string());
+    game_state.init_links(&mut input);
 
+
+    // game loop
+    loop {
+        game_state.init_entities(&mut input);
+
+        /*
+        let mut max_game = game_state.random_search();
         max_game.compute_bomb();
         max_game.print_commands();
 
@@ -723,16 +3062,21 @@ fn main() {
 
         */
         //game_state.neutral_first_strategy();
-        game_state.defend_strategy();
-        game_state.compute_bomb();
-        game_state.print_commands();
+        game_state.play_turn(Brain::Rules);
+        let output = game_state.take_commands();
+        println!("{}", output);
 
         game_state.nb_turn += 1;
         
 
-        let elapsed = start.elapsed();
-        print_err!("Elapsed: {} ms",
-             (elapsed.as_secs() * 1_000) + (elapsed.subsec_nanos() / 1_000_000) as u64);
+        let elapsed_ms = game_state.budget.elapsed_ms();
+        trace!(Category::Timing, Level::Info, "elapsed", "ms" => elapsed_ms);
 
+        input.record('O', &output);
+        input.record('T', &elapsed_ms.to_string());
     }
 }
+
+fn main() {
+    crate::run(std::env::args().collect());
+}
@@ -0,0 +1,3 @@
+fn main() {
+    gitc::run(std::env::args().collect());
+}
@@ -0,0 +1,484 @@
+#![allow(clippy::needless_return)]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// Codingame only accepts a single source file: inline every `mod x;` of the
+// gitc library into `mod x { ... }`, drop the tests and the debug only code,
+// and append the bot entry point.
+// `cargo run -p bundler -- [--allow crate]... [output]`, ai.rs by default
+
+const HEADER: &str = "// Generated by `cargo run -p bundler` from gitc/ and bot/, do not edit.\n";
+
+// Items behind these cfg are not part of the submission
+const STRIPPED_CFGS: [&str; 3] = ["#[cfg(test)]", "#[cfg(debug_assertions)]", "#[test]"];
+
+// Crates available in the contest IDE, more with `--allow name`
+const ALLOWED_CRATES: [&str; 3] = ["std", "core", "alloc"];
+
+fn workspace_dir() -> PathBuf {
+    return Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().to_path_buf();
+}
+
+// Source file of module `name` declared in `dir`: name.rs or name/mod.rs
+fn module_path(dir: &Path, name: &str) -> PathBuf {
+    let file = dir.join(format!("{}.rs", name));
+    if file.exists() {
+        return file;
+    }
+    return dir.join(name).join("mod.rs");
+}
+
+// Declaration `[pub] mod name;` or `[pub(crate)] mod name;`, returns (prefix, name)
+fn parse_mod_decl(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim();
+    let rest = trimmed.strip_suffix(';')?;
+    let pos = rest.find("mod ")?;
+    let prefix = &rest[..pos];
+    if !(prefix.is_empty() || prefix.starts_with("pub")) {
+        return None;
+    }
+    let name = rest[pos + 4..].trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    return Some((prefix, name));
+}
+
+fn is_stripped_cfg(line: &str) -> bool {
+    let trimmed: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    return STRIPPED_CFGS.iter().any(|&cfg| trimmed == cfg);
+}
+
+// Line by line view of a source where strings, char literals and comments are blanked out
+#[derive(Default)]
+struct Lexer {
+    in_string: bool,
+    in_block_comment: bool
+}
+
+impl Lexer {
+    // `line` with spaces in place of what is not code
+    fn code(&mut self, line: &str) -> String {
+        let chars: Vec<char> = line.chars().collect();
+        let mut code = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).cloned();
+            let start = i;
+            let mut kept = false;
+            if self.in_block_comment {
+                if c == '*' && next == Some('/') { self.in_block_comment = false; i += 1; }
+            } else if self.in_string {
+                if c == '\\' { i += 1; }
+                else if c == '"' { self.in_string = false; }
+            } else if c == '/' && next == Some('/') {
+                break;
+            } else if c == '/' && next == Some('*') {
+                self.in_block_comment = true;
+                i += 1;
+            } else if c == '"' {
+                self.in_string = true;
+            } else if c == '\'' && next == Some('\\') {
+                // '\n', '\'' or '\u{..}'
+                i += 3;
+                while i < chars.len() && chars[i] != '\'' { i += 1; }
+            } else if c == '\'' && chars.get(i + 2) == Some(&'\'') {
+                i += 2;
+            } else {
+                // Code, the quote of a lifetime included
+                kept = true;
+            }
+            i += 1;
+            if kept {
+                code.push(c);
+            } else {
+                code.extend((start..i.min(chars.len())).map(|_| ' '));
+            }
+        }
+        return code;
+    }
+}
+
+// Brace depth tracking that ignores strings, char literals and comments
+#[derive(Default)]
+struct BraceScanner {
+    depth: i32,
+    opened: bool, // At least one brace seen
+    lexer: Lexer
+}
+
+impl BraceScanner {
+    fn scan(&mut self, line: &str) {
+        for c in self.lexer.code(line).chars() {
+            if c == '{' {
+                self.depth += 1;
+                self.opened = true;
+            } else if c == '}' {
+                self.depth -= 1;
+            }
+        }
+    }
+}
+
+// Index of the line after the item starting at `start`, its attributes included
+fn skip_item(lines: &[&str], start: usize) -> usize {
+    let mut scanner = BraceScanner::default();
+    let mut i = start;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if !scanner.opened && line.trim_start().starts_with("#[") { continue }
+
+        scanner.scan(line);
+        if scanner.opened && scanner.depth <= 0 { break }
+        if !scanner.opened && line.trim_end().ends_with(';') { break }
+    }
+    return i;
+}
+
+// `dir` is where the submodules of the module in `path` live, inlined module names go to `modules`
+fn inline_file(path: &Path, dir: &Path, out: &mut String, modules: &mut Vec<String>) {
+    let source = fs::read_to_string(path).unwrap_or_else(|e| panic!("cannot read {}: {}", path.display(), e));
+    let lines: Vec<&str> = source.lines().collect();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if is_stripped_cfg(line) {
+            i = skip_item(&lines, i + 1);
+            continue;
+        }
+        match parse_mod_decl(line) {
+            Some((prefix, name)) => {
+                let child = module_path(dir, name);
+                let child_dir = dir.join(name);
+                modules.push(name.to_string());
+                out.push_str(&format!("{}mod {} {{\n", prefix, name));
+                inline_file(&child, &child_dir, out, modules);
+                out.push_str("}\n");
+            },
+            None => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        i += 1;
+    }
+}
+
+// Names a path can start with without naming a crate: keywords, primitive types, the prelude and the tool attributes
+const BUILTIN_ROOTS: [&str; 46] = [
+    "crate", "self", "super", "Self",
+    "bool", "char", "str", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize", "f32", "f64",
+    "Option", "Some", "None", "Result", "Ok", "Err", "Vec", "String", "Box", "ToString", "ToOwned", "Iterator", "IntoIterator",
+    "Default", "Clone", "From", "Into", "PartialEq", "PartialOrd", "Eq", "Ord", "Fn", "FnMut",
+    "clippy", "rustfmt"
+];
+
+// Keywords that can come before an absolute `::name` path
+const PATH_KEYWORDS: [&str; 15] = ["as", "break", "dyn", "else", "for", "if", "impl", "in", "let", "match", "move", "mut", "return", "where", "while"];
+
+// Items whose name becomes local, generic parameters follow the name
+const DECLARATIONS: [&str; 9] = ["mod", "struct", "enum", "union", "trait", "type", "fn", "const", "static"];
+
+fn is_ident(token: &str) -> bool {
+    return token.starts_with(|c: char| c.is_alphabetic() || c == '_');
+}
+
+// (line, token) of the identifiers, `::` and other punctuation of the code
+fn tokens(source: &str) -> Vec<(usize, String)> {
+    let mut lexer = Lexer::default();
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let chars: Vec<char> = lexer.code(line).chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c.is_alphanumeric() || c == '_' {
+                // Identifiers, and numbers which are dropped
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') { i += 1; }
+                if !c.is_numeric() { tokens.push((index + 1, chars[start..i].iter().collect())); }
+                continue;
+            }
+            if c == ':' && chars.get(i + 1) == Some(&':') {
+                tokens.push((index + 1, "::".to_string()));
+                i += 1;
+            } else if !c.is_whitespace() {
+                tokens.push((index + 1, c.to_string()));
+            }
+            i += 1;
+        }
+    }
+    return tokens;
+}
+
+// First segments of the paths that can name a crate, and the names declared or imported by the code
+#[derive(Default)]
+struct Paths {
+    roots: Vec<(usize, String)>,
+    local: BTreeSet<String>
+}
+
+impl Paths {
+    fn scan(tokens: &[(usize, String)]) -> Paths {
+        let mut paths = Paths::default();
+        let token = |i: usize| tokens.get(i).map(|token| token.1.as_str()).unwrap_or("");
+        let mut i = 0;
+        while i < tokens.len() {
+            let (line, current) = (tokens[i].0, token(i));
+            if current == "use" {
+                i = paths.scan_use(tokens, i + 1);
+                continue;
+            }
+
+            if current == "extern" && token(i + 1) == "crate" {
+                paths.roots.push((line, token(i + 2).to_string()));
+            } else if DECLARATIONS.contains(&current) && is_ident(token(i + 1)) {
+                paths.local.insert(token(i + 1).to_string());
+                paths.scan_generics(tokens, i + 2);
+            } else if current == "impl" {
+                paths.scan_generics(tokens, i + 1);
+            } else if current == "::" && is_ident(token(i + 1)) {
+                // `::name` is absolute, after an identifier or a `>` it continues a path
+                let previous = if i > 0 { token(i - 1) } else { "" };
+                if (!is_ident(previous) || PATH_KEYWORDS.contains(&previous)) && previous != ">" {
+                    paths.roots.push((line, token(i + 1).to_string()));
+                }
+            } else if is_ident(current) && !PATH_KEYWORDS.contains(&current) && token(i + 1) == "::" && (i == 0 || !["::", ".", "$"].contains(&token(i - 1))) {
+                // Not a segment, a turbofish method or a macro variable
+                paths.roots.push((line, current.to_string()));
+            }
+            i += 1;
+        }
+        return paths;
+    }
+
+    // The use tree starting at `i`: the first name of the tree and of the groups without a prefix are roots,
+    // the other names are imported. Returns the index after the `;`
+    fn scan_use(&mut self, tokens: &[(usize, String)], mut i: usize) -> usize {
+        let mut groups: Vec<bool> = Vec::new(); // Group opened where a root is expected
+        let mut at_root = true;
+        while i < tokens.len() && tokens[i].1 != ";" {
+            let (line, token) = (tokens[i].0, tokens[i].1.as_str());
+            match token {
+                "{" => groups.push(at_root),
+                "," => at_root = *groups.last().unwrap_or(&false),
+                "}" => { groups.pop(); at_root = false; },
+                "::" => (),
+                _ if is_ident(token) && at_root => { self.roots.push((line, token.to_string())); at_root = false; },
+                _ if is_ident(token) => { self.local.insert(token.to_string()); },
+                _ => at_root = false
+            }
+            i += 1;
+        }
+        return i + 1;
+    }
+
+    // Names of the generic parameters in the `<...>` starting at `i`
+    fn scan_generics(&mut self, tokens: &[(usize, String)], i: usize) {
+        if tokens.get(i).map(|token| token.1.as_str()) != Some("<") { return }
+        let mut depth = 0;
+        for j in i..tokens.len() {
+            match tokens[j].1.as_str() {
+                "<" => depth += 1,
+                ">" if tokens[j - 1].1 != "-" => depth -= 1,
+                name if depth == 1 && is_ident(name) && ["<", ","].contains(&tokens[j - 1].1.as_str()) => { self.local.insert(name.to_string()); },
+                _ => ()
+            }
+            if depth == 0 { break }
+        }
+    }
+}
+
+// "line N: crate x" for every crate the contest does not provide, in `use`, `extern crate` and paths
+fn check_crates(bundled: &str, modules: &[String], allowed: &[String]) -> Vec<String> {
+    let lines: Vec<&str> = bundled.lines().collect();
+    let paths = Paths::scan(&tokens(bundled));
+    let mut errors = Vec::new();
+    for (line, name) in paths.roots.iter() {
+        let local = BUILTIN_ROOTS.contains(&name.as_str()) || paths.local.contains(name) || modules.contains(name);
+        let allowed = ALLOWED_CRATES.contains(&name.as_str()) || allowed.contains(name);
+        let error = format!("line {}: crate {} is not available in the contest: {}", line, name, lines[line - 1].trim());
+        if !local && !allowed && !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+    return errors;
+}
+
+// "line N: crate x" for the dependencies of a Cargo.toml the contest does not provide, gitc is inlined
+fn check_manifest(manifest: &str, allowed: &[String]) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut in_dependencies = false;
+    for (index, line) in manifest.lines().enumerate() {
+        let entry = line.split('#').next().unwrap().trim();
+        let name = if entry.starts_with('[') {
+            // [dependencies], [target.'cfg(..)'.dependencies] or a [dependencies.name] table
+            let section = entry.trim_start_matches('[').trim_end_matches(']');
+            in_dependencies = section == "dependencies" || section.ends_with(".dependencies");
+            match section.strip_prefix("dependencies.").or_else(|| section.split_once(".dependencies.").map(|(_, name)| name)) {
+                Some(name) => name,
+                None => continue
+            }
+        } else if in_dependencies && entry.contains('=') {
+            entry.split('=').next().unwrap()
+        } else {
+            continue;
+        };
+
+        let name = name.trim().trim_matches('"');
+        if name != "gitc" && !ALLOWED_CRATES.contains(&name) && !allowed.iter().any(|crate_name| crate_name == name) {
+            errors.push(format!("line {}: crate {} is not available in the contest: {}", index + 1, name, line.trim()));
+        }
+    }
+    return errors;
+}
+
+fn bundle(root: &Path, allowed: &[String]) -> Result<String, Vec<String>> {
+    let mut out = String::from(HEADER);
+    let mut modules = Vec::new();
+    let lib_dir = root.join("gitc").join("src");
+    inline_file(&lib_dir.join("lib.rs"), &lib_dir, &mut out, &mut modules);
+
+    let main = fs::read_to_string(root.join("bot").join("src").join("main.rs")).expect("cannot read bot/src/main.rs");
+    out.push('\n');
+    out.push_str(&main.replace("gitc::", "crate::"));
+
+    let mut errors = Vec::new();
+    for package in ["gitc", "bot"].iter() {
+        let manifest = fs::read_to_string(root.join(package).join("Cargo.toml")).unwrap_or_else(|e| panic!("cannot read {}/Cargo.toml: {}", package, e));
+        errors.extend(check_manifest(&manifest, allowed).into_iter().map(|error| format!("{}/Cargo.toml {}", package, error)));
+    }
+    errors.extend(check_crates(&out, &modules, allowed));
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    return Ok(out);
+}
+
+const USAGE: &str = "Usage: cargo run -p bundler -- [--allow crate]... [output]";
+
+// (allowed crates, output path), an unknown flag or a second output path is an error
+fn parse_args(args: &[String]) -> Result<(Vec<String>, Option<PathBuf>), String> {
+    let mut allowed = Vec::new();
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--allow" {
+            match args.next() {
+                Some(name) => allowed.push(name.clone()),
+                None => return Err("--allow needs a crate name".to_string())
+            }
+        } else if arg.starts_with('-') {
+            return Err(format!("Unknown option {}", arg));
+        } else if output.is_some() {
+            return Err(format!("More than one output path: {}", arg));
+        } else {
+            output = Some(PathBuf::from(arg));
+        }
+    }
+    return Ok((allowed, output));
+}
+
+fn main() {
+    let root = workspace_dir();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (allowed, output) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+    let output = output.unwrap_or_else(|| root.join("ai.rs"));
+
+    let bundled = match bundle(&root, &allowed) {
+        Ok(bundled) => bundled,
+        Err(errors) => {
+            for error in errors.iter() {
+                eprintln!("{}", error);
+            }
+            process::exit(1);
+        }
+    };
+    fs::write(&output, &bundled).unwrap_or_else(|e| panic!("cannot write {}: {}", output.display(), e));
+    eprintln!("Wrote {} ({} lines)", output.display(), bundled.lines().count());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modules_and_debug_code_are_stripped() {
+        let lines = vec![
+            "#[cfg(test)]",
+            "mod tests {",
+            "    fn brace() -> char { return '}'; }",
+            "    const TEXT: &str = \"{ {\";",
+            "}",
+            "fn kept() {}"
+        ];
+        assert_eq!(lines[skip_item(&lines, 1)], "fn kept() {}");
+
+        let lines = vec!["#[allow(dead_code)]", "fn debug<'a>(x: &'a str) -> &'a str {", "    x", "}", "fn kept() {}"];
+        assert_eq!(lines[skip_item(&lines, 0)], "fn kept() {}");
+
+        let lines = vec!["use std::io::Write;", "fn debug() { print_err!(\"{}\", 1); }", "fn kept() {}"];
+        assert_eq!(lines[skip_item(&lines, 0)], "fn debug() { print_err!(\"{}\", 1); }");
+        assert_eq!(lines[skip_item(&lines, 1)], "fn kept() {}");
+
+        assert!(is_stripped_cfg("    #[cfg( test )]"));
+        assert!(!is_stripped_cfg("#[cfg(not(test))]"));
+    }
+
+    #[test]
+    fn external_crates_are_reported() {
+        let bundled = "use std::io;\nuse crate::model::GameState;\nuse $crate::trace::TraceValue;\nextern crate rand;\nmod model {\n    use super::trace;\n    pub use ::trace::Level;\n}\n";
+        let modules = vec!["model".to_string(), "trace".to_string()];
+
+        assert_eq!(check_crates(bundled, &modules, &[]), vec!["line 4: crate rand is not available in the contest: extern crate rand;"]);
+        assert!(check_crates(bundled, &modules, &["rand".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn crates_used_by_path_are_reported() {
+        let bundled = "fn main() {\n    let x: u8 = rand::random();\n    let y = &mut ::rand::thread_rng();\n    let s = \"regex::Regex\"; // serde::Value\n    let v = Vec::<i32>::new().iter().sum::<i32>() + i32::MAX;\n    std::process::exit(io::stdin());\n}\nuse std::io;\nfn new<T: Default>() -> T { T::default() }\n";
+
+        assert_eq!(check_crates(bundled, &[], &[]), vec!["line 2: crate rand is not available in the contest: let x: u8 = rand::random();",
+                                                         "line 3: crate rand is not available in the contest: let y = &mut ::rand::thread_rng();"]);
+    }
+
+    #[test]
+    fn grouped_imports_are_split() {
+        let bundled = "use {std::io, std::fs};\nuse {\n    std::{cmp, mem},\n    rand as random,\n};\nuse ::{core::mem::swap};\n";
+
+        assert_eq!(check_crates(bundled, &[], &[]), vec!["line 4: crate rand is not available in the contest: rand as random,"]);
+    }
+
+    #[test]
+    fn manifest_dependencies_are_reported() {
+        let manifest = "[package]\nname = \"bot\"\n\n[dependencies]\ngitc = { path = \"../gitc\" } # inlined\nrand = \"0.8\"\n\n[dev-dependencies]\nproptest = \"1\"\n\n[dependencies.regex]\nversion = \"1\"\n";
+
+        assert_eq!(check_manifest(manifest, &[]), vec!["line 6: crate rand is not available in the contest: rand = \"0.8\"",
+                                                       "line 11: crate regex is not available in the contest: [dependencies.regex]"]);
+        assert!(check_manifest(manifest, &["rand".to_string(), "regex".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn unknown_flags_and_extra_outputs_are_rejected() {
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(parse_args(&args(&["--allow", "rand", "out.rs"])), Ok((vec!["rand".to_string()], Some(PathBuf::from("out.rs")))));
+        assert_eq!(parse_args(&args(&[])), Ok((Vec::new(), None)));
+        assert!(parse_args(&args(&["--help"])).is_err());
+        assert!(parse_args(&args(&["-o", "out.rs"])).is_err());
+        assert!(parse_args(&args(&["a.rs", "b.rs"])).is_err());
+        assert!(parse_args(&args(&["--allow"])).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner, Troop};
+use crate::territory::Zone;
+use crate::threat::Threat;
+use crate::trace::{Category, Level};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Purpose {
+    Capture,
+    Reinforce,
+    Feint // One cyborg to an enemy factory, worth feint_value
+}
+
+impl Purpose {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Purpose::Capture => "capture",
+            Purpose::Reinforce => "reinforce",
+            Purpose::Feint => "feint"
+        }
+    }
+}
+
+// One possible order of a factory, its value uses the evaluator weights
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub purpose: Purpose,
+    pub to: i32,
+    pub count: i32,
+    pub value: f32
+}
+
+// Candidates to take within `spare` cyborgs and `max_orders` orders for the most value, a knapsack on both
+pub fn best_split(candidates: &[Candidate], spare: i32, max_orders: i32) -> Vec<usize> {
+    let (spare, max_orders) = (spare.max(0) as usize, max_orders.max(0) as usize);
+    // best[i][c][o]: value of the first i candidates with c cyborgs and o orders at most
+    let mut best = vec![vec![vec![0.0f32; max_orders + 1]; spare + 1]; candidates.len() + 1];
+    for (i, candidate) in candidates.iter().enumerate() {
+        let count = candidate.count as usize;
+        for c in 0..=spare {
+            for o in 0..=max_orders {
+                best[i + 1][c][o] = best[i][c][o];
+                if count <= c && o > 0 && best[i][c - count][o - 1] + candidate.value > best[i + 1][c][o] {
+                    best[i + 1][c][o] = best[i][c - count][o - 1] + candidate.value;
+                }
+            }
+        }
+    }
+
+    let mut chosen = Vec::new();
+    let (mut c, mut o) = (spare, max_orders);
+    for i in (0..candidates.len()).rev() {
+        if best[i + 1][c][o] != best[i][c][o] {
+            chosen.push(i);
+            c -= candidates[i].count as usize;
+            o -= 1;
+        }
+    }
+    chosen.reverse();
+    return chosen;
+}
+
+impl GameState {
+    // Owner and garrison a troop sent now meets, after the production and the troops landing first
+    pub fn garrison_at_arrival(&self, id: i32, arrival: i32) -> (i32, i32) {
+        let mut factory = self.factories.get(&id).unwrap().clone();
+        for turn in 1..=arrival {
+            factory.produce();
+            let (mut player_count, mut enemy_count) = (0, 0);
+            for troop in self.troops.iter() {
+                if troop.factory_end != id || troop.turn_remaining != turn { continue }
+                if troop.is_player() {
+                    player_count += troop.cyborg_count;
+                } else {
+                    enemy_count += troop.cyborg_count;
+                }
+            }
+            factory.resolve_arrivals(player_count, enemy_count);
+        }
+        return (factory.owner, factory.cyborg_count);
+    }
+
+    // Orders worth sending from `from`, the cyborgs needed are counted at the arrival
+    pub fn move_candidates(&self, from: i32, threats: &BTreeMap<i32, Threat>) -> Vec<Candidate> {
+        let weights = self.params.eval;
+        let factory = self.factories.get(&from).unwrap();
+        let mut candidates = Vec::new();
+        for &(distance, id) in factory.distances.iter() {
+            let target = self.factories.get(&id).unwrap();
+            let arrival = distance + 1;
+            // Production lasts until the lookahead
+            let discount = (self.params.lookahead - arrival).max(0) as f32 / self.params.lookahead as f32;
+
+            // The garrison killed of an enemy factory is an even trade, its production changes side
+            let enemy_value = 2.0 * weights.production * target.production as f32 * discount;
+            if target.is_neutral() && target.production > 0 {
+                match self.garrison_at_arrival(id, arrival) {
+                    (0, garrison) => {
+                        let value = weights.production * target.production as f32 * discount - weights.owned_cyborgs * garrison as f32;
+                        candidates.push(Candidate{purpose: Purpose::Capture, to: id, count: garrison + 1, value: value});
+                    },
+                    // An enemy troop takes it first
+                    (-1, garrison) => {
+                        let count = garrison + self.enemy_reinforcements(id, arrival) + 1;
+                        candidates.push(Candidate{purpose: Purpose::Capture, to: id, count: count, value: enemy_value});
+                    },
+                    _ => ()
+                }
+            } else if target.is_enemy() {
+                let count = self.counter_garrison(id, arrival) + 1;
+                if count > 0 && target.production > 0 {
+                    candidates.push(Candidate{purpose: Purpose::Capture, to: id, count: count, value: enemy_value});
+                }
+                if self.params.feint_value > weights.owned_cyborgs {
+                    candidates.push(Candidate{purpose: Purpose::Feint, to: id, count: 1, value: self.params.feint_value - weights.owned_cyborgs});
+                }
+            } else if let Some(threat) = threats.get(&id) {
+                let need = threat.required_garrison - target.cyborg_count;
+                if need > 0 && arrival <= threat.deadline {
+                    candidates.push(Candidate{purpose: Purpose::Reinforce, to: id, count: need, value: 2.0 * weights.production * target.production as f32 * discount});
+                }
+            }
+        }
+        candidates.retain(|candidate| candidate.value > 0.0);
+        return candidates;
+    }
+
+    // Split the spare cyborgs of each frontline factory among several targets, the biggest spares choose first
+    // and a target is only served once
+    pub fn allocate_moves(&mut self) {
+        if self.params.max_orders == 0 { return }
+        let threats = self.compute_threats(self.params.threat_horizon);
+        let mut sources: Vec<(i32, i32)> = self.factories.values()
+            .filter(|factory| factory.is_player() && factory.cyborg_remaining > 0 && self.territory.zone(factory.id) != Zone::Backline)
+            .map(|factory| (factory.id, factory.cyborg_remaining))
+            .collect();
+        sources.sort_by_key(|&(id, spare)| (-spare, id));
+
+        let mut served: Vec<i32> = self.troop_commands.iter().map(|troop| troop.factory_end).collect();
+        for &(from, spare) in sources.iter() {
+            let candidates: Vec<Candidate> = self.move_candidates(from, &threats).into_iter()
+                .filter(|candidate| !served.contains(&candidate.to))
+                .collect();
+
+            for i in best_split(&candidates, spare, self.params.max_orders) {
+                let candidate = &candidates[i];
+                served.push(candidate.to);
+                self.factories.get_mut(&from).unwrap().cyborg_remaining -= candidate.count;
+                self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: from, factory_end: candidate.to, cyborg_count: candidate.count, turn_remaining: self.distance(from, candidate.to)});
+                trace!(Category::Attack, Level::Info, "move", "from" => from, "to" => candidate.to, "count" => candidate.count, "value" => candidate.value,
+                       "reason" => candidate.purpose.name());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{new_map, orders, troop};
+
+    fn candidate(to: i32, count: i32, value: f32) -> Candidate {
+        return Candidate{purpose: Purpose::Capture, to: to, count: count, value: value};
+    }
+
+    #[test]
+    fn split_beats_the_single_best_target() {
+        let candidates = [candidate(1, 10, 30.0), candidate(2, 6, 20.0), candidate(3, 4, 15.0)];
+
+        assert_eq!(best_split(&candidates, 10, 3), vec![1, 2]);
+        assert_eq!(best_split(&candidates, 10, 1), vec![0]);
+        assert_eq!(best_split(&candidates, 3, 3), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn spare_cyborgs_go_to_several_neutrals() {
+        // 0 is ours with 12 spare cyborgs, 1 and 2 are neutrals, the enemy 3 is far away
+        let mut state = new_map(&[(0, 1, 12, 1), (1, 0, 4, 2), (2, 0, 5, 1), (3, -1, 40, 3)],
+                                &[(0, 1, 2), (0, 2, 3), (1, 2, 4), (0, 3, 15), (1, 3, 15), (2, 3, 15)]);
+        state.update_territory();
+        state.allocate_moves();
+
+        assert_eq!(orders(&state), vec![(0, 1, 5), (0, 2, 6)]);
+        assert_eq!(state.factories.get(&0).unwrap().cyborg_remaining, 1);
+    }
+
+    #[test]
+    fn neutrals_taken_first_by_the_enemy_are_enemy_captures() {
+        // 8 enemy cyborgs land on the 5 of factory 1 next turn, 2 more turns of production before we arrive
+        let mut state = new_map(&[(0, 1, 30, 1), (1, 0, 5, 2), (2, -1, 40, 3)], &[(0, 1, 2), (0, 2, 15), (1, 2, 15)]);
+        state.troops.push_back(troop(-1, 2, 1, 8, 1));
+        assert_eq!(state.garrison_at_arrival(1, 3), (-1, 7));
+
+        let candidates = state.move_candidates(0, &BTreeMap::new());
+        let capture = candidates.iter().find(|candidate| candidate.to == 1).unwrap();
+        assert_eq!((capture.purpose, capture.count), (Purpose::Capture, 8));
+        assert_eq!(capture.value, 2.0 * 10.0 * 2.0 * 17.0 / 20.0);
+    }
+}
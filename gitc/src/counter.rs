@@ -0,0 +1,117 @@
+use crate::model::{GameState, HasOwner, Troop};
+use crate::trace::{Category, Level};
+
+impl GameState {
+    // Cyborgs an enemy factory launched since the last turn, its troops still at the full distance
+    pub fn just_launched(&self, id: i32) -> i32 {
+        return self.troops.iter()
+            .filter(|troop| troop.is_enemy() && troop.factory_start == id && troop.turn_remaining == self.distance(troop.factory_start, troop.factory_end))
+            .map(|troop| troop.cyborg_count)
+            .sum();
+    }
+
+    // Worst case garrison when a troop sent now arrives: the projected garrison plus every enemy factory that could reinforce it first
+    pub fn counter_garrison(&self, id: i32, arrival: i32) -> i32 {
+        return self.projected_garrison(id, arrival) + self.enemy_reinforcements(id, arrival);
+    }
+
+    // Garrisons of the enemy factories whose troops sent now land before `arrival`
+    pub fn enemy_reinforcements(&self, id: i32, arrival: i32) -> i32 {
+        let mut reinforcements = 0;
+        for &(distance, id2) in self.factories.get(&id).unwrap().distances.iter() {
+            if distance + 1 >= arrival { break }
+            let other = self.factories.get(&id2).unwrap();
+            if other.is_enemy() { reinforcements += other.cyborg_count }
+        }
+        return reinforcements;
+    }
+
+    // An enemy factory that launched more than it kept is hit from the closest factory able to take it before any help comes
+    pub fn counter_attack(&mut self) {
+        let mut targets: Vec<(i32, i32)> = self.factories.values()
+            .filter(|factory| factory.is_enemy())
+            .map(|factory| (factory.id, self.just_launched(factory.id)))
+            .filter(|&(id, launched)| launched > 0 && launched >= self.factories.get(&id).unwrap().cyborg_count)
+            .collect();
+        targets.sort_by_key(|&(id, _)| (-self.factories.get(&id).unwrap().production, id));
+
+        for &(id, launched) in targets.iter() {
+            let mut order = None;
+            for &(distance, id2) in self.factories.get(&id).unwrap().distances.iter() {
+                let source = self.factories.get(&id2).unwrap();
+                if !source.is_player() { continue }
+                let count = self.counter_garrison(id, distance + 1) + 1;
+                if count <= source.cyborg_remaining {
+                    order = Some((id2, distance, count));
+                    break;
+                }
+            }
+
+            let (from, distance, count) = match order {
+                Some(order) => order,
+                None => continue
+            };
+            self.factories.get_mut(&from).unwrap().cyborg_remaining -= count;
+            self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: from, factory_end: id, cyborg_count: count, turn_remaining: distance});
+            trace!(Category::Attack, Level::Info, "move", "from" => from, "to" => id, "count" => count, "launched" => launched,
+                   "reason" => "counterattack a factory the enemy just emptied");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{new_map, orders, troop};
+
+    // Enemy 1 sent 20 of its cyborgs to 2, we are 0 three turns from 1 and enemy 3 is far away
+    fn state() -> GameState {
+        let mut state = new_map(&[(0, 1, 30, 1), (1, -1, 5, 2), (2, 1, 40, 1), (3, -1, 50, 3)],
+                                &[(0, 1, 3), (0, 2, 4), (1, 2, 6), (0, 3, 8), (1, 3, 9), (2, 3, 8)]);
+        state.troops.push_back(troop(-1, 1, 2, 20, 6));
+        return state;
+    }
+
+    #[test]
+    fn emptied_factories_are_counterattacked() {
+        let mut state = state();
+        state.counter_attack();
+
+        // 5 + 4 turns of production of 2, plus one
+        assert_eq!(orders(&state), vec![(0, 1, 14)]);
+        assert_eq!(state.factories.get(&0).unwrap().cyborg_remaining, 16);
+    }
+
+    #[test]
+    fn old_launches_and_strong_garrisons_are_ignored() {
+        let mut state = state();
+        state.troops.front_mut().unwrap().turn_remaining = 4;
+        state.counter_attack();
+        assert!(state.troop_commands.is_empty());
+
+        let mut state = self::state();
+        state.factories.get_mut(&1).unwrap().cyborg_count = 25;
+        state.counter_attack();
+        assert!(state.troop_commands.is_empty());
+    }
+
+    #[test]
+    fn enemy_reinforcements_arriving_first_count() {
+        let mut state = state();
+        state.factory_distance.insert((1, 3), 2);
+        state.init_factories_distance(4);
+        let owners = [(1, 30), (-1, 5), (1, 40), (-1, 50)];
+        for (id, &(owner, count)) in owners.iter().enumerate() {
+            let factory = state.factories.get_mut(&(id as i32)).unwrap();
+            factory.owner = owner;
+            factory.cyborg_count = count;
+            factory.production = 2;
+            factory.cyborg_remaining = count;
+        }
+
+        // 5 + 8 produced + 50 from factory 3 is more than we have
+        assert_eq!(state.counter_garrison(1, 4), 63);
+        state.counter_attack();
+        assert!(state.troop_commands.is_empty());
+    }
+}
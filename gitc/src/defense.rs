@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner, Troop};
+use crate::threat::Threat;
+use crate::trace::{Category, Level};
+
+// Reinforcements chosen by allocate_defense
+#[derive(Default)]
+pub struct DefensePlan {
+    pub orders: Vec<(i32, i32, i32)>, // (from, to, count)
+    pub abandoned: Vec<i32> // Factories that cannot be saved in time
+}
+
+impl GameState {
+    // Each threatened factory needs its worst deficit, and by each turn t the cyborgs arrived by t must cover
+    // the deficit of turn t. The closest senders first meet every deadline with the fewest cyborgs. The most
+    // productive factories are served first, a factory that cannot be saved is abandoned without using anyone.
+    pub fn allocate_defense(&self, threats: &BTreeMap<i32, Threat>) -> DefensePlan {
+        let mut spare: BTreeMap<i32, i32> = self.factories.values()
+            .filter(|factory| factory.is_player())
+            .map(|factory| (factory.id, factory.cyborg_remaining.max(0)))
+            .collect();
+
+        // (turn, deficit) of the turns the garrison falls short
+        let mut demands: Vec<(i32, Vec<(i32, i32)>)> = Vec::new();
+        for (&id, threat) in threats.iter() {
+            let garrison = self.factories.get(&id).unwrap().cyborg_count;
+            let deficits: Vec<(i32, i32)> = (1..threat.enemy.len() as i32)
+                .map(|turn| (turn, threat.deficit(turn, garrison)))
+                .filter(|&(_, deficit)| deficit > 0)
+                .collect();
+            if !deficits.is_empty() { demands.push((id, deficits)) }
+        }
+        demands.sort_by_key(|&(id, ref deficits)| {
+            let need = deficits.iter().map(|&(_, deficit)| deficit).max().unwrap();
+            (-self.factories.get(&id).unwrap().production, need, id)
+        });
+
+        let mut plan = DefensePlan::default();
+        for (id, deficits) in demands.iter() {
+            let factory = self.factories.get(id).unwrap();
+            let mut need = deficits.iter().map(|&(_, deficit)| deficit).max().unwrap();
+
+            let mut orders = Vec::new();
+            for &(distance, id2) in factory.distances.iter() {
+                if need == 0 { break }
+                let available = spare.get(&id2).cloned().unwrap_or(0);
+                if available == 0 { continue }
+                let count = available.min(need);
+                need -= count;
+                orders.push((id2, distance + 1, count));
+            }
+
+            let in_time = deficits.iter().all(|&(turn, deficit)| {
+                orders.iter().filter(|&&(_, arrival, _)| arrival <= turn).map(|&(_, _, count)| count).sum::<i32>() >= deficit
+            });
+            if need > 0 || !in_time {
+                plan.abandoned.push(*id);
+                continue;
+            }
+
+            for &(from, _, count) in orders.iter() {
+                *spare.get_mut(&from).unwrap() -= count;
+                plan.orders.push((from, *id, count));
+            }
+        }
+        return plan;
+    }
+
+    // Reinforce the factories under attack that can be saved, the others hold until the turn before impact and evacuate
+    pub fn reinforce(&mut self) {
+        let threats = self.compute_threats(0);
+        let plan = self.allocate_defense(&threats);
+        for &(from, to, count) in plan.orders.iter() {
+            let distance = self.distance(from, to);
+            self.factories.get_mut(&from).unwrap().cyborg_remaining -= count;
+            self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: from, factory_end: to, cyborg_count: count, turn_remaining: distance});
+            trace!(Category::Defend, Level::Info, "move", "from" => from, "to" => to, "count" => count, "distance" => distance, "reason" => "reinforce");
+        }
+
+        for &id in plan.abandoned.iter() {
+            let deadline = threats.get(&id).unwrap().deadline;
+            if deadline <= 1 {
+                self.evacuate(id, &plan.abandoned);
+            } else {
+                let factory = self.factories.get_mut(&id).unwrap();
+                trace!(Category::Defend, Level::Info, "abandon", "factory" => id, "garrison" => factory.cyborg_count, "deadline" => deadline,
+                       "reason" => "cannot be saved in time, hold until the turn before impact");
+                factory.cyborg_remaining = 0;
+            }
+        }
+    }
+
+    // Enemy cyborgs left at the arrival of a troop sent now, our troops on their way included
+    pub fn projected_garrison(&self, id: i32, arrival: i32) -> i32 {
+        let factory = self.factories.get(&id).unwrap();
+        let mut garrison = factory.cyborg_count + factory.production * (arrival - factory.disrupted).max(0);
+        for troop in self.troops.iter() {
+            if troop.factory_end != id || troop.turn_remaining > arrival { continue }
+            garrison += troop.cyborg_count * troop.owner * factory.owner;
+        }
+        return garrison;
+    }
+
+    // The whole garrison leaves before the enemy arrives: it captures the most productive enemy factory it can,
+    // or joins the closest factory that is not lost. The factory is traded, not defended.
+    pub fn evacuate(&mut self, id: i32, doomed: &[i32]) {
+        let factory = self.factories.get(&id).unwrap();
+        let garrison = factory.cyborg_count;
+        if garrison <= 0 { return }
+
+        let mut target = None;
+        for &(distance, id2) in factory.distances.iter() {
+            let other = self.factories.get(&id2).unwrap();
+            if !other.is_enemy() || self.projected_garrison(id2, distance + 1) >= garrison { continue }
+            let better = match target {
+                Some((_, production, _)) => other.production > production,
+                None => true
+            };
+            if better {
+                target = Some((id2, other.production, "counterattack"));
+            }
+        }
+        if target.is_none() {
+            target = factory.distances.iter()
+                .find(|&&(_, id2)| self.factories.get(&id2).unwrap().is_player() && !doomed.contains(&id2))
+                .map(|&(_, id2)| (id2, 0, "join the closest safe factory"));
+        }
+
+        let (to, _, reason) = match target {
+            Some(target) => target,
+            None => return
+        };
+        let distance = self.distance(id, to);
+        let production = factory.production;
+        self.factories.get_mut(&id).unwrap().cyborg_remaining = 0;
+        self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: id, factory_end: to, cyborg_count: garrison, turn_remaining: distance});
+        trace!(Category::Defend, Level::Info, "trade", "factory" => id, "production" => production, "to" => to, "count" => garrison, "reason" => reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{new_map, orders, troop};
+
+    // 0 is attacked, 1 is 2 turns away and 2 is 5 turns away
+    fn state(attack: i32, arrival: i32) -> GameState {
+        let mut state = new_map(&[(0, 1, 5, 1), (1, 1, 10, 1), (2, 1, 30, 1), (3, -1, 0, 0)], &[(0, 1, 2), (0, 2, 5), (1, 2, 6), (0, 3, 9), (1, 3, 9), (2, 3, 9)]);
+        state.troops.push_back(troop(-1, 3, 0, attack, arrival));
+        return state;
+    }
+
+    fn plan(state: &GameState) -> DefensePlan {
+        return state.allocate_defense(&state.compute_threats(0));
+    }
+
+    #[test]
+    fn closest_senders_cover_the_deficit_exactly() {
+        // 25 arrive at turn 7 against 5 + 7 produced: 13 are missing, factory 2 arrives at turn 6
+        let plan = plan(&state(25, 7));
+
+        assert_eq!(plan.orders, vec![(1, 0, 10), (2, 0, 3)]);
+        assert!(plan.abandoned.is_empty());
+    }
+
+    #[test]
+    fn late_reinforcements_do_not_count() {
+        // Only factory 1 arrives by turn 3, its 10 cyborgs are not enough
+        let plan = plan(&state(25, 3));
+
+        assert!(plan.orders.is_empty());
+        assert_eq!(plan.abandoned, vec![0]);
+    }
+
+    #[test]
+    fn doomed_factories_hold_until_the_turn_before_impact() {
+        let mut state = state(25, 3);
+        state.reinforce();
+
+        assert!(state.troop_commands.is_empty());
+        assert_eq!(state.factories.get(&0).unwrap().cyborg_remaining, 0);
+    }
+
+    #[test]
+    fn doomed_factories_evacuate_to_the_closest_safe_factory() {
+        let mut state = state(40, 1);
+        state.factories.get_mut(&3).unwrap().cyborg_count = 20;
+        state.reinforce();
+
+        assert_eq!(orders(&state), vec![(0, 1, 5)]);
+        assert_eq!(state.factories.get(&0).unwrap().cyborg_remaining, 0);
+    }
+
+    #[test]
+    fn doomed_factories_counterattack_a_weak_enemy() {
+        // Factory 3 is empty and does not produce
+        let mut counterattack = state(40, 1);
+        counterattack.reinforce();
+        assert_eq!(counterattack.troop_commands.front().unwrap().factory_end, 3);
+
+        let mut reinforced = state(40, 1);
+        reinforced.troops.push_back(troop(-1, 1, 3, 6, 2));
+        reinforced.reinforce();
+        assert_eq!(reinforced.troop_commands.front().unwrap().factory_end, 1);
+    }
+}
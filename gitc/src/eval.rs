@@ -0,0 +1,212 @@
+use std::env;
+use std::fs;
+
+use crate::model::{GameState, HasOwner};
+use crate::params::parse_config;
+
+pub const TERMINAL_SCORE: f32 = 1_000_000.0; // Above any evaluation of a running game
+
+// Weights of the evaluation function, each term is computed as player minus enemy
+#[derive(Clone, Copy)]
+pub struct Evaluator {
+    pub owned_cyborgs: f32,
+    pub troops: f32,
+    pub production: f32,
+    pub projected_captures: f32, // Production that changes side when the troops in flight land, owned production is not counted again
+    pub centrality: f32,
+    pub bombs: f32, // Bombs left, ours minus the enemy's as inferred from its launches
+    pub frontline: f32
+}
+
+impl Evaluator {
+    pub fn new() -> Evaluator {
+        Evaluator {
+            owned_cyborgs: 1.0,
+            troops: 1.0,
+            production: 10.0,
+            projected_captures: 10.0,
+            centrality: 0.0,
+            bombs: 0.0,
+            frontline: 0.0
+        }
+    }
+
+    // GITC_EVAL_FILE is a file with one "name=weight" per line, GITC_EVAL is "name=weight,name=weight" and wins over the file
+    pub fn load() -> Evaluator {
+        return Evaluator::load_from(env::var("GITC_EVAL_FILE").ok(), env::var("GITC_EVAL").ok());
+    }
+
+    pub fn load_from(path: Option<String>, config: Option<String>) -> Evaluator {
+        let mut evaluator = Evaluator::new();
+
+        if let Some(path) = path {
+            match fs::read_to_string(&path) {
+                Ok(config) => evaluator.parse(&config),
+                Err(err) => print_err!("[EVAL] Cannot read {}: {}", path, err)
+            }
+        }
+
+        if let Some(config) = config {
+            evaluator.parse(&config);
+        }
+
+        return evaluator;
+    }
+
+    pub fn parse(&mut self, config: &str) {
+        for (key, value) in parse_config(config) {
+            if !self.set(&key, value) { print_err!("[EVAL] Unknown weight {}", key) }
+        }
+    }
+
+    pub fn entries(&self) -> Vec<(&'static str, f32)> {
+        return vec![
+            ("owned_cyborgs", self.owned_cyborgs),
+            ("troops", self.troops),
+            ("production", self.production),
+            ("projected_captures", self.projected_captures),
+            ("centrality", self.centrality),
+            ("bombs", self.bombs),
+            ("frontline", self.frontline)
+        ];
+    }
+
+    pub fn set(&mut self, key: &str, value: f32) -> bool {
+        match key {
+            "owned_cyborgs" => self.owned_cyborgs = value,
+            "troops" => self.troops = value,
+            "production" => self.production = value,
+            "projected_captures" => self.projected_captures = value,
+            "centrality" => self.centrality = value,
+            "bombs" => self.bombs = value,
+            "frontline" => self.frontline = value,
+            _ => return false
+        }
+        return true;
+    }
+}
+
+impl GameState {
+    pub fn evaluate(&self) -> f32 {
+        if self.is_terminal() {
+            return self.winner() as f32 * TERMINAL_SCORE;
+        }
+
+        let weights = &self.params.eval;
+        let mut owned_cyborgs = 0;
+        let mut production = 0;
+        let mut centrality: f32 = 0.0;
+        let mut frontline: f32 = 0.0;
+
+        // Cyborg in factories
+        for factory in self.factories.values() {
+            if factory.is_neutral() { continue }
+            let side = factory.owner;
+
+            owned_cyborgs += factory.cyborg_count * side;
+            production += factory.production * side;
+
+            // Close to everything is better
+            for &(distance, _) in factory.distances.iter() {
+                centrality += side as f32 / distance as f32;
+            }
+
+            // Cyborg advantage over each enemy factory, the closer the more pressure
+            if factory.is_player() {
+                for &(distance, id2) in factory.distances.iter() {
+                    let enemy_fac = self.factories.get(&id2).unwrap();
+                    if !enemy_fac.is_enemy() { continue }
+                    frontline += (factory.cyborg_count - enemy_fac.cyborg_count) as f32 / distance as f32;
+                }
+            }
+        }
+
+        // Cyborg in troops
+        let mut troops = 0;
+        for troop in self.troops.iter() {
+            troops += troop.cyborg_count * troop.owner;
+        }
+
+        // Factories that change side when the troops in flight land, the neutral garrison fights the arrivals
+        let mut projected_captures = 0;
+        for factory in self.factories.values() {
+            let mut arrivals = 0;
+            for troop in self.troops.iter() {
+                if factory.id == troop.factory_end {
+                    arrivals += troop.cyborg_count * troop.owner;
+                }
+            }
+
+            let projected_owner = if factory.is_neutral() {
+                if arrivals.abs() > factory.cyborg_count { arrivals.signum() } else { 0 }
+            } else {
+                let cyborg_count = factory.cyborg_count * factory.owner + arrivals;
+                if cyborg_count == 0 { factory.owner } else { cyborg_count.signum() }
+            };
+            // Production gained minus production lost, a factory taken from the enemy counts twice
+            projected_captures += (projected_owner - factory.owner) * factory.production;
+        }
+
+        let score = weights.owned_cyborgs * owned_cyborgs as f32
+            + weights.troops * troops as f32
+            + weights.production * production as f32
+            + weights.projected_captures * projected_captures as f32
+            + weights.centrality * centrality
+            + weights.bombs * (self.bomb_count - self.enemy_bomb_count) as f32
+            + weights.frontline * frontline;
+
+        //print_err!("Score : {}", score);
+        return score;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{new_state, troop};
+
+    // Only the projected captures count
+    fn state(factories: &[(i32, i32, i32, i32)]) -> GameState {
+        let mut state = new_state(factories, 3);
+        for (key, _) in state.params.eval.entries() {
+            state.params.eval.set(key, 0.0);
+        }
+        state.params.eval.projected_captures = 1.0;
+        return state;
+    }
+
+    #[test]
+    fn owned_production_is_not_a_capture() {
+        let state = state(&[(0, 1, 10, 3), (1, -1, 10, 2), (2, 0, 20, 3)]);
+        assert_eq!(state.evaluate(), 0.0);
+    }
+
+    #[test]
+    fn captures_must_beat_the_garrison() {
+        let mut state = state(&[(0, 1, 30, 3), (1, -1, 10, 2), (2, 0, 20, 3)]);
+        state.troops.push_back(troop(1, 0, 2, 1, 2));
+        assert_eq!(state.evaluate(), 0.0);
+
+        state.troops.push_back(troop(1, 0, 2, 20, 2));
+        assert_eq!(state.evaluate(), 3.0);
+
+        // The enemy factory changes side, its production counts for both
+        state.troops.push_back(troop(1, 0, 1, 11, 2));
+        assert_eq!(state.evaluate(), 3.0 + 2.0 * 2.0);
+
+        // Our factory is lost
+        state.troops.push_back(troop(-1, 1, 0, 31, 2));
+        assert_eq!(state.evaluate(), 3.0 + 2.0 * 2.0 - 2.0 * 3.0);
+    }
+
+    #[test]
+    fn environment_weights_override_the_file() {
+        let path = env::temp_dir().join(format!("gitc_eval_{}.txt", std::process::id()));
+        fs::write(&path, "# weights\nproduction=5\nbombs = 2\n").unwrap();
+        let evaluator = Evaluator::load_from(Some(path.to_string_lossy().to_string()), Some("bombs=3, frontline=0.5,unknown=1,troops".to_string()));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((evaluator.production, evaluator.bombs, evaluator.frontline, evaluator.troops), (5.0, 3.0, 0.5, 1.0));
+        assert_eq!(Evaluator::load_from(Some("/nonexistent/gitc_eval".to_string()), None).production, Evaluator::new().production);
+    }
+}
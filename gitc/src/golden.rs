@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fs;
+
+use crate::model::{Bomb, Factory, GameState, HasOwner, Troop};
+use crate::protocol::Input;
+
+// One turn of a golden game: the entities sent to player 1 and the commands of both players
+struct GoldenTurn {
+    entities: Vec<String>,
+    commands: Vec<(i32, String)>
+}
+
+// Recorded game, see golden/: "LINK a b distance" lines, then for each turn "TURN", "E <entity line>" and "CMD <1|-1> <command>"
+fn parse_golden_game(text: &str) -> (Vec<(i32, i32, i32)>, Vec<GoldenTurn>) {
+    let mut links = Vec::new();
+    let mut turns: Vec<GoldenTurn> = Vec::new();
+    for line in text.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') { continue }
+        let (tag, value) = line.split_at(line.find(' ').unwrap_or(line.len()));
+        let value = value.trim();
+        match tag {
+            "LINK" => {
+                let ids: Vec<i32> = value.split_whitespace().map(|id| parse_input!(id, i32)).collect();
+                links.push((ids[0], ids[1], ids[2]));
+            },
+            "TURN" => turns.push(GoldenTurn{entities: Vec::new(), commands: Vec::new()}),
+            "E" => turns.last_mut().expect("Entity before the first TURN").entities.push(value.to_string()),
+            "CMD" => {
+                let (side, command) = value.split_at(value.find(' ').unwrap_or(value.len()));
+                turns.last_mut().expect("Command before the first TURN").commands.push((parse_input!(side, i32), command.trim().to_string()));
+            },
+            _ => panic!("Unknown golden game line {}", line)
+        }
+    }
+    return (links, turns);
+}
+
+fn troop_summary(troop: &Troop) -> (i32, i32, i32, i32, i32) {
+    return (troop.owner, troop.factory_start, troop.factory_end, troop.cyborg_count, troop.turn_remaining);
+}
+
+// The target of enemy bombs is hidden, like in the referee input
+fn bomb_summary(bomb: &Bomb) -> (i32, i32, i32, i32) {
+    if bomb.is_enemy() {
+        return (bomb.owner, bomb.factory_start, -1, -1);
+    }
+    return (bomb.owner, bomb.factory_start, bomb.factory_end, bomb.turn_remaining);
+}
+
+// Elements of `expected` missing from `simulated` and the other way around
+pub fn diff_entities<T: PartialEq + Clone>(expected: &[T], simulated: &[T]) -> (Vec<T>, Vec<T>) {
+    let mut unexpected = simulated.to_vec();
+    let mut missing = Vec::new();
+    for entity in expected.iter() {
+        match unexpected.iter().position(|other| other == entity) {
+            Some(position) => { unexpected.remove(position); },
+            None => missing.push(entity.clone())
+        }
+    }
+    return (missing, unexpected);
+}
+
+// Replay sim_next_turn from each recorded turn with the recorded commands and report every difference with the next turn
+pub fn check_golden_game(text: &str) -> Vec<String> {
+    let (links, turns) = parse_golden_game(text);
+    let mut base = GameState::new();
+    for &(id1, id2, distance) in links.iter() {
+        base.factory_distance.insert((id1, id2), distance);
+    }
+    let factory_count = links.iter().map(|&(id1, id2, _)| id1.max(id2)).max().unwrap_or(-1) + 1;
+    base.init_factories_distance(factory_count);
+
+    let load = |turn: &GoldenTurn| {
+        let mut lines: VecDeque<String> = turn.entities.iter().cloned().collect();
+        lines.push_front(turn.entities.len().to_string());
+        let mut state = base.clone();
+        state.init_entities(&mut Input::replay(lines));
+        state
+    };
+
+    let mut reports = Vec::new();
+    let mut enemy_bombs: HashMap<i32, (i32, i32)> = HashMap::new(); // id -> (target, turns) learned from the enemy BOMB commands
+    for (t, turn) in turns.iter().enumerate().take(turns.len().saturating_sub(1)) {
+        let mut state = load(turn);
+        state.nb_turn = t as i32;
+        for bomb in state.bombs.iter_mut().filter(|bomb| bomb.is_enemy()) {
+            if let Some(&(target, turn_remaining)) = enemy_bombs.get(&bomb.id) {
+                bomb.factory_end = target;
+                bomb.turn_remaining = turn_remaining;
+            }
+        }
+        for &(side, ref command) in turn.commands.iter() {
+            state.push_order(side, command);
+        }
+        let known_bombs: Vec<i32> = state.bombs.iter().map(|bomb| bomb.id).collect();
+        state.sim_next_turn();
+
+        let expected = load(&turns[t + 1]);
+        let prefix = format!("turn {} -> {}:", t, t + 1);
+
+        for (id, factory) in expected.factories.iter() {
+            let simulated = state.factories.get(id).unwrap();
+            let summary = |fac: &Factory| format!("owner {} cyborgs {} production {} disrupted {}", fac.owner, fac.cyborg_count, fac.production, fac.disrupted);
+            if summary(factory) != summary(simulated) {
+                reports.push(format!("{} factory {} expected {}, simulated {}", prefix, id, summary(factory), summary(simulated)));
+            }
+        }
+
+        let expected_troops: Vec<_> = expected.troops.iter().map(troop_summary).collect();
+        let simulated_troops: Vec<_> = state.troops.iter().map(troop_summary).collect();
+        let (missing, unexpected) = diff_entities(&expected_troops, &simulated_troops);
+        for &(label, troops) in [("missing", &missing), ("unexpected", &unexpected)].iter() {
+            for &(owner, start, end, count, turn_remaining) in troops.iter() {
+                reports.push(format!("{} {} troop owner {} {}->{} cyborgs {} turns {}", prefix, label, owner, start, end, count, turn_remaining));
+            }
+        }
+
+        // Learn where the new enemy bombs go, the expected ids are matched by source factory
+        for bomb in state.bombs.iter().filter(|bomb| bomb.is_enemy()) {
+            let id = if known_bombs.contains(&bomb.id) { Some(bomb.id) } else {
+                expected.bombs.iter()
+                    .find(|other| other.is_enemy() && other.factory_start == bomb.factory_start && !enemy_bombs.contains_key(&other.id))
+                    .map(|other| other.id)
+            };
+            if let Some(id) = id {
+                enemy_bombs.insert(id, (bomb.factory_end, bomb.turn_remaining));
+            }
+        }
+
+        let expected_bombs: Vec<_> = expected.bombs.iter().map(bomb_summary).collect();
+        let simulated_bombs: Vec<_> = state.bombs.iter().map(bomb_summary).collect();
+        let (missing, unexpected) = diff_entities(&expected_bombs, &simulated_bombs);
+        for &(label, bombs) in [("missing", &missing), ("unexpected", &unexpected)].iter() {
+            for &(owner, start, end, turn_remaining) in bombs.iter() {
+                reports.push(format!("{} {} bomb owner {} {}->{} turns {}", prefix, label, owner, start, end, turn_remaining));
+            }
+        }
+    }
+
+    return reports;
+}
+
+// Check recorded games against the simulator: `cargo run --release -p bot -- golden <file>...`
+pub fn golden(args: &[String]) {
+    for path in args.iter() {
+        let reports = match fs::read_to_string(path) {
+            Ok(text) => check_golden_game(&text),
+            Err(err) => { print_err!("Cannot read {}: {}", path, err); continue }
+        };
+
+        println!("{}: {}", path, if reports.is_empty() { "ok".to_string() } else { format!("{} differences", reports.len()) });
+        for report in reports.iter() {
+            println!("  {}", report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Written by hand from the referee rules, not recorded from the official engine: they only check the simulator
+    // against our reading of the rules
+    const RULES_FIXTURES: [(&str, &str); 1] = [
+        ("skirmish", include_str!("../golden/rules/skirmish.txt"))
+    ];
+
+    // Games recorded from Codingame matches, in golden/recorded/. None is checked in yet, so engine parity is untested
+    const GOLDEN_GAMES: [(&str, &str); 0] = [];
+
+    fn assert_simulator_matches(games: &[(&str, &str)]) {
+        for &(name, game) in games.iter() {
+            let reports = check_golden_game(game);
+            assert!(reports.is_empty(), "{}:\n{}", name, reports.join("\n"));
+        }
+    }
+
+    #[test]
+    fn simulator_matches_hand_written_rules_fixtures() {
+        assert_simulator_matches(&RULES_FIXTURES);
+    }
+
+    #[test]
+    fn simulator_matches_recorded_golden_games() {
+        assert_simulator_matches(&GOLDEN_GAMES);
+    }
+
+    #[test]
+    fn golden_game_differences_are_reported() {
+        let game = RULES_FIXTURES[0].1.replace("E 1 FACTORY 1 5 1 0 0", "E 1 FACTORY 1 6 1 0 0");
+        let reports = check_golden_game(&game);
+
+        assert_eq!(reports[0], "turn 0 -> 1: factory 1 expected owner 1 cyborgs 6 production 1 disrupted 0, simulated owner 1 cyborgs 5 production 1 disrupted 0");
+    }
+}
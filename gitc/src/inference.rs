@@ -0,0 +1,148 @@
+use std::collections::BTreeSet;
+
+use crate::golden::diff_entities;
+use crate::model::{GameState, HasOwner, Snapshot, Troop, BOMB_DISRUPTION};
+use crate::trace::{Category, Level};
+
+// Enemy commands of the last turn, rebuilt from what they changed. The simulation of the last turn with
+// these commands and ours must give the current turn, any difference is a simulator discrepancy.
+#[derive(Clone, Default)]
+pub struct EnemyActions {
+    pub moves: Vec<(i32, i32, i32)>, // (from, to, count) of the new enemy troops
+    pub incs: Vec<i32>, // Enemy factories one production higher
+    pub bombs: Vec<i32>, // Sources of the new enemy bombs, the target stays hidden
+    pub explosions: Vec<i32>, // Factories disrupted again when an enemy bomb disappeared
+    pub discrepancies: Vec<String>
+}
+
+impl GameState {
+    // The last turn as the simulator sees it: its entities and our orders
+    pub fn restore(&self, last: &Snapshot) -> GameState {
+        let mut state = self.clone();
+        state.nb_turn = last.nb_turn;
+        state.factories = last.factories.clone();
+        state.troops = last.troops.clone();
+        state.bombs = last.bombs.clone();
+        state.commands.clear();
+        state.troop_commands.clear();
+        for order in last.orders.iter() {
+            state.push_order(1, order);
+        }
+        return state;
+    }
+
+    pub fn infer_enemy_actions(&self, last: &Snapshot) -> EnemyActions {
+        let mut actions = EnemyActions::default();
+
+        let known_troops: BTreeSet<i32> = last.troops.iter().map(|troop| troop.id).collect();
+        for troop in self.troops.iter() {
+            if troop.is_enemy() && !known_troops.contains(&troop.id) {
+                actions.moves.push((troop.factory_start, troop.factory_end, troop.cyborg_count));
+            }
+        }
+
+        for factory in self.factories.values() {
+            let before = last.factories.get(&factory.id).unwrap();
+            if factory.is_enemy() && before.is_enemy() && factory.production == before.production + 1 {
+                actions.incs.push(factory.id);
+            }
+        }
+
+        let known_bombs: BTreeSet<i32> = last.bombs.iter().map(|bomb| bomb.id).collect();
+        let bombs: BTreeSet<i32> = self.bombs.iter().map(|bomb| bomb.id).collect();
+        actions.bombs = self.bombs.iter()
+            .filter(|bomb| bomb.is_enemy() && !known_bombs.contains(&bomb.id))
+            .map(|bomb| bomb.factory_start)
+            .collect();
+        let exploded = last.bombs.iter().filter(|bomb| bomb.is_enemy() && !bombs.contains(&bomb.id)).count();
+
+        let mut predicted = self.restore(last);
+        for &(from, to, count) in actions.moves.iter() {
+            predicted.troop_commands.push_back(Troop{id: 999, owner: -1, factory_start: from, factory_end: to, cyborg_count: count, turn_remaining: 0});
+        }
+        for &id in actions.incs.iter() {
+            predicted.commands.push(format!("INC {}", id));
+        }
+        predicted.sim_next_turn();
+
+        // The explosions come last, their targets are the factories newly disrupted
+        for factory in self.factories.values() {
+            if actions.explosions.len() == exploded { break }
+            let simulated = predicted.factories.get_mut(&factory.id).unwrap();
+            if factory.disrupted == BOMB_DISRUPTION && simulated.disrupted != BOMB_DISRUPTION {
+                simulated.explode_bomb();
+                actions.explosions.push(factory.id);
+            }
+        }
+
+        actions.discrepancies = predicted.discrepancies(self);
+        return actions;
+    }
+
+    // Factories and troops of `seen` that differ from this simulated state, new troops are matched by content
+    pub fn discrepancies(&self, seen: &GameState) -> Vec<String> {
+        let mut discrepancies = Vec::new();
+        for (id, factory) in seen.factories.iter() {
+            let simulated = self.factories.get(id).unwrap();
+            let summary = (factory.owner, factory.cyborg_count, factory.production, factory.disrupted);
+            let simulated_summary = (simulated.owner, simulated.cyborg_count, simulated.production, simulated.disrupted);
+            if summary != simulated_summary {
+                discrepancies.push(format!("factory {} {:?} simulated {:?}", id, summary, simulated_summary));
+            }
+        }
+
+        let troop_summary = |troop: &Troop| (troop.owner, troop.factory_start, troop.factory_end, troop.cyborg_count, troop.turn_remaining);
+        let seen_troops: Vec<_> = seen.troops.iter().map(troop_summary).collect();
+        let simulated_troops: Vec<_> = self.troops.iter().map(troop_summary).collect();
+        let (missing, unexpected) = diff_entities(&seen_troops, &simulated_troops);
+        for troop in missing.iter() {
+            discrepancies.push(format!("troop {:?} not simulated", troop));
+        }
+        for troop in unexpected.iter() {
+            discrepancies.push(format!("troop {:?} simulated only", troop));
+        }
+        return discrepancies;
+    }
+
+    // Called by update_profile with the previous turn
+    pub fn update_enemy_actions(&mut self, last: &Snapshot) {
+        let actions = self.infer_enemy_actions(last);
+        self.enemy_bomb_count -= actions.bombs.len() as i32;
+
+        trace!(Category::Opponent, Level::Info, "actions", "moves" => actions.moves.iter().map(|&(from, to, count)| format!("{} {} {}", from, to, count)).collect::<Vec<_>>(),
+               "incs" => actions.incs.clone(), "bombs" => actions.bombs.clone(), "explosions" => actions.explosions.clone(), "bombs_left" => self.enemy_bomb_count);
+        for discrepancy in actions.discrepancies.iter() {
+            trace!(Category::Opponent, Level::Info, "discrepancy", "turn" => last.nb_turn, "detail" => discrepancy.clone());
+        }
+        self.enemy_actions = actions;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Bomb;
+    use crate::testing::referee_turn;
+
+    #[test]
+    fn enemy_commands_explain_the_next_turn() {
+        let (last, next) = referee_turn();
+        let actions = next.infer_enemy_actions(&last);
+
+        assert_eq!(actions.moves, vec![(1, 2, 8)]);
+        assert_eq!(actions.incs, vec![1]);
+        assert!(actions.bombs.is_empty());
+        assert_eq!(actions.explosions, vec![0]);
+        assert!(actions.discrepancies.is_empty(), "{:?}", actions.discrepancies);
+    }
+
+    #[test]
+    fn unexplained_changes_are_discrepancies() {
+        let (last, mut next) = referee_turn();
+        next.factories.get_mut(&1).unwrap().cyborg_count = 20;
+        next.bombs.push(Bomb{id: 7, owner: -1, factory_start: 1, factory_end: -1, turn_remaining: -1});
+        next.update_enemy_actions(&last);
+
+        assert_eq!(next.enemy_actions.discrepancies, vec!["factory 1 (-1, 20, 2, 0) simulated (-1, 14, 2, 0)".to_string()]);
+        assert_eq!((next.enemy_actions.bombs.clone(), next.enemy_bomb_count), (vec![1], 1));
+    }
+}
@@ -0,0 +1,122 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::new_without_default, clippy::collapsible_if)]
+
+macro_rules! print_err {
+    ($($arg:tt)*) => (
+        {
+            use std::io::Write;
+            writeln!(&mut ::std::io::stderr(), $($arg)*).ok();
+        }
+    )
+}
+
+// Structured decision trace, see `Tracer`: trace!(Category::Defend, Level::Info, "event", "key" => value, ...)
+macro_rules! trace {
+    ($category:expr, $level:expr, $event:expr $(, $key:expr => $value:expr)*) => (
+        if $crate::trace::trace_enabled($category, $level) {
+            use $crate::trace::TraceValue;
+            let fields: Vec<(&str, String)> = vec![$(($key, ($value).to_json())),*];
+            $crate::trace::trace_write($category, $level, $event, &fields);
+        }
+    )
+}
+
+macro_rules! parse_input {
+    ($x:expr, $t:ident) => ($x.trim().parse::<$t>().unwrap())
+}
+
+pub mod trace;
+pub mod rng;
+pub mod time;
+pub mod eval;
+pub mod params;
+pub mod model;
+pub mod protocol;
+pub mod sim;
+pub mod territory;
+pub mod logistics;
+pub mod threat;
+pub mod defense;
+pub mod counter;
+pub mod profile;
+pub mod inference;
+pub mod simcheck;
+pub mod tracking;
+pub mod allocation;
+pub mod strategy;
+pub mod selfplay;
+pub mod replay;
+pub mod golden;
+#[cfg(test)]
+mod testing;
+
+use std::env;
+
+use crate::model::GameState;
+use crate::params::Params;
+use crate::protocol::Input;
+use crate::rng::{startup_seed, Rng};
+use crate::strategy::Brain;
+use crate::trace::{Category, Level};
+
+// Contest bot reading the referee on stdin, or one of the local modes: replay, tune, tournament, golden
+pub fn run(mut args: Vec<String>) {
+    let has_seed = args.iter().any(|arg| arg == "--seed") || env::var("GITC_SEED").is_ok();
+    if args.len() > 1 && args[1] == "replay" {
+        // The recorded seed unless one is given
+        let seed = if has_seed { Some(startup_seed(&mut args)) } else { None };
+        replay::replay(&args[2..], seed);
+        return;
+    }
+
+    let seed = startup_seed(&mut args);
+    if args.len() > 1 && args[1] == "tune" {
+        selfplay::tune(&args[2..], seed);
+        return;
+    }
+    if args.len() > 1 && args[1] == "tournament" {
+        selfplay::tournament(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "golden" {
+        golden::golden(&args[2..]);
+        return;
+    }
+
+    let mut game_state: GameState = GameState::new();
+    game_state.rng = Rng::new(seed);
+    game_state.params = Params::load();
+    game_state.bomb_count = game_state.params.bomb_count;
+
+    let mut input = Input::stdin();
+    input.record('P', &game_state.params.to_config().trim().replace('\n', ","));
+    input.record('S', &seed.to_string());
+    game_state.init_links(&mut input);
+
+
+    // game loop
+    loop {
+        game_state.init_entities(&mut input);
+
+        /*
+        let mut max_game = game_state.random_search();
+        max_game.compute_bomb();
+        max_game.print_commands();
+
+        game_state = max_game;
+
+        */
+        //game_state.neutral_first_strategy();
+        game_state.play_turn(Brain::Rules);
+        let output = game_state.take_commands();
+        println!("{}", output);
+
+        game_state.nb_turn += 1;
+        
+
+        let elapsed_ms = game_state.budget.elapsed_ms();
+        trace!(Category::Timing, Level::Info, "elapsed", "ms" => elapsed_ms);
+
+        input.record('O', &output);
+        input.record('T', &elapsed_ms.to_string());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::model::{GameState, HasOwner, Troop};
+use crate::territory::{Zone, NO_FACTORY};
+use crate::trace::{Category, Level};
+
+// Fastest routes between factories hopping only through the factories of one side.
+// A troop cannot be redirected, so it is sent to the next hop and forwarded from there on the next turns:
+// it lands at the end of a turn and leaves on the next one, each hop costs one more turn.
+pub struct Routes {
+    length: Vec<Vec<i32>>,
+    hops: Vec<Vec<i32>>,
+    next: Vec<Vec<i32>>
+}
+
+impl Routes {
+    // Sum of the legs plus one turn per hop, NO_FACTORY when there is no route
+    pub fn length(&self, from: i32, to: i32) -> i32 {
+        return self.length[from as usize][to as usize];
+    }
+
+    pub fn next_hop(&self, from: i32, to: i32) -> i32 {
+        return self.next[from as usize][to as usize];
+    }
+}
+
+impl GameState {
+    // Floyd-Warshall, on equal length fewer hops are better
+    pub fn compute_routes(&self, side: i32) -> Routes {
+        let count = self.factories.len();
+        let mut routes = Routes{length: vec![vec![NO_FACTORY; count]; count], hops: vec![vec![0; count]; count], next: vec![vec![-1; count]; count]};
+        for factory in self.factories.values() {
+            let i = factory.id as usize;
+            routes.length[i][i] = 0;
+            routes.next[i][i] = factory.id;
+            for &(distance, id2) in factory.distances.iter() {
+                routes.length[i][id2 as usize] = distance;
+                routes.hops[i][id2 as usize] = 1;
+                routes.next[i][id2 as usize] = id2;
+            }
+        }
+
+        for hop in self.factories.values() {
+            if hop.owner != side { continue }
+            let k = hop.id as usize;
+            for i in 0..count {
+                for j in 0..count {
+                    if i == j || i == k || j == k { continue }
+                    let length = routes.length[i][k] + routes.length[k][j] + 1;
+                    let hops = routes.hops[i][k] + routes.hops[k][j];
+                    if length < routes.length[i][j] || (length == routes.length[i][j] && hops < routes.hops[i][j]) {
+                        routes.length[i][j] = length;
+                        routes.hops[i][j] = hops;
+                        routes.next[i][j] = routes.next[i][k];
+                    }
+                }
+            }
+        }
+        return routes;
+    }
+
+    // Backline factories keep backline_garrison cyborgs and stream the rest to the closest frontline factory
+    pub fn supply_frontline(&mut self) {
+        let frontline: Vec<i32> = self.factories.values()
+            .filter(|factory| factory.is_player() && self.territory.zone(factory.id) == Zone::Frontline)
+            .map(|factory| factory.id)
+            .collect();
+        if frontline.is_empty() { return }
+
+        let routes = self.compute_routes(1);
+        for factory in self.factories.values_mut() {
+            if !factory.is_player() || self.territory.zone(factory.id) != Zone::Backline { continue }
+
+            let surplus = factory.cyborg_remaining - self.params.backline_garrison;
+            if surplus <= 0 { continue }
+
+            // Closest by route, then closest to the enemy
+            let target = *frontline.iter()
+                .min_by_key(|&&id| (routes.length(factory.id, id), self.territory.get(id).enemy_distance, id))
+                .unwrap();
+            if routes.length(factory.id, target) >= NO_FACTORY { continue }
+
+            let hop = routes.next_hop(factory.id, target);
+            factory.cyborg_remaining -= surplus;
+            self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory.id, factory_end: hop, cyborg_count: surplus, turn_remaining: routes.length(factory.id, hop)});
+            trace!(Category::Logistics, Level::Info, "move", "from" => factory.id, "to" => hop, "count" => surplus, "frontline" => target,
+                   "route_length" => routes.length(factory.id, target), "reason" => "backline surplus to the frontline");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{new_map, orders};
+
+    // 0 - 1 - 2 - 3 on a line with 3 turns between each, the direct links are longer
+    fn line_map(owners: [i32; 4]) -> GameState {
+        let factories: Vec<_> = owners.iter().enumerate().map(|(id, &owner)| (id as i32, owner, 20, 1)).collect();
+        let links = [(0, 1, 3), (1, 2, 3), (2, 3, 3), (0, 2, 8), (1, 3, 7), (0, 3, 10)];
+        let mut state = new_map(&factories, &links);
+        state.update_territory();
+        return state;
+    }
+
+    #[test]
+    fn routes_hop_through_our_factories_only() {
+        let state = line_map([1, 1, 0, -1]);
+        let routes = state.compute_routes(1);
+
+        // 3 + 3 turns and one more to leave 1 beat the direct 8 turns
+        assert_eq!((routes.length(0, 2), routes.next_hop(0, 2)), (7, 1));
+        // 2 is neutral, the 11 turns through 1 are slower than the direct link
+        assert_eq!((routes.length(0, 3), routes.next_hop(0, 3)), (10, 3));
+        assert_eq!(state.compute_routes(-1).next_hop(0, 2), 2);
+
+        // Same length through 2, the direct link has fewer hops
+        let routes = line_map([1, 1, 1, -1]).compute_routes(1);
+        assert_eq!((routes.length(1, 3), routes.next_hop(1, 3)), (7, 3));
+    }
+
+    #[test]
+    fn backline_surplus_goes_to_the_next_hop() {
+        let mut state = line_map([1, 1, 1, -1]);
+        state.supply_frontline();
+
+        assert_eq!(orders(&state), vec![(0, 1, 15), (1, 2, 15)]);
+        assert_eq!(state.factories.get(&2).unwrap().cyborg_remaining, 20);
+    }
+}
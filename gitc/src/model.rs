@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::collections::LinkedList;
+use std::rc::Rc;
+
+use crate::inference::EnemyActions;
+use crate::params::Params;
+use crate::profile::OpponentProfile;
+use crate::rng::Rng;
+use crate::territory::Territory;
+use crate::time::TimeBudget;
+use crate::tracking::TrackedTroop;
+use crate::trace::{Category, Level};
+
+pub const MAX_TURNS: i32 = 200;
+pub const MAX_PRODUCTION: i32 = 3;
+pub const INC_COST: i32 = 10;
+pub const BOMB_DISRUPTION: i32 = 5;
+
+#[derive(Clone)]
+pub struct Factory {
+    pub id: i32,
+    pub owner: i32,
+    pub cyborg_count: i32,
+    pub production: i32,
+    pub distances: Vec<(i32, i32)>, // (distance, id)
+    pub disrupted: i32, // Turns before the production resumes after a bomb
+    pub cyborg_remaining: i32 // For random strategy
+}
+
+pub trait HasOwner {
+    fn get_owner(&self) -> i32;
+
+
+    fn is_player(&self) -> bool {
+        return self.get_owner() == 1;
+    }
+
+    fn is_enemy(&self) -> bool {
+        return self.get_owner() == -1;
+    }
+
+    fn is_neutral(&self) -> bool {
+        return self.get_owner() == 0;
+    }
+}
+
+impl HasOwner for Factory {
+    fn get_owner(&self) -> i32 { self.owner }
+}
+
+#[derive(Clone)]
+pub struct Troop {
+    pub id: i32,
+    pub owner: i32,
+    pub factory_start: i32,
+    pub factory_end: i32,
+    pub cyborg_count: i32,
+    pub turn_remaining: i32
+}
+
+impl HasOwner for Troop {
+    fn get_owner(&self) -> i32 { self.owner }
+}
+
+// The target and the remaining turns of enemy bombs are unknown (-1)
+#[derive(Clone)]
+pub struct Bomb {
+    pub id: i32,
+    pub owner: i32,
+    pub factory_start: i32,
+    pub factory_end: i32,
+    pub turn_remaining: i32
+}
+
+impl HasOwner for Bomb {
+    fn get_owner(&self) -> i32 { self.owner }
+}
+
+#[derive(Clone)]
+pub struct GameState {
+    pub factory_distance: HashMap<(i32, i32), i32>,
+    pub factories: BTreeMap<i32, Factory>, // Sorted so that strategies are deterministic
+    pub troops: LinkedList<Troop>,
+    pub bombs: Vec<Bomb>,
+    pub commands: Vec<String>,
+    pub bomb_count: i32,
+    pub bomb_last: i32,
+    pub troop_commands: LinkedList<Troop>,
+    pub rng: Rng,
+    pub nb_turn: i32,
+    pub bombing: bool,
+    pub params: Params,
+    pub budget: TimeBudget,
+    pub territory: Territory, // Refreshed at the start of each turn
+    pub next_entity_id: i32, // Id of the next troop or bomb created by the simulator
+    pub last_seen: Option<Rc<Snapshot>>, // Previous turn, compared with the current one
+    pub profile: OpponentProfile,
+    pub enemy_actions: EnemyActions, // Inferred from the last turn
+    pub enemy_bomb_count: i32,
+    pub tracked_troops: BTreeMap<i32, TrackedTroop> // Troops in flight by entity id
+}
+
+// What the referee showed on a turn
+#[derive(Clone)]
+pub struct Snapshot {
+    pub nb_turn: i32,
+    pub factories: BTreeMap<i32, Factory>,
+    pub troops: LinkedList<Troop>,
+    pub bombs: Vec<Bomb>,
+    pub orders: Vec<String> // Our commands of that turn
+}
+
+impl GameState {
+    pub fn new() -> GameState {
+        GameState {
+            factory_distance: HashMap::new(),
+            factories: BTreeMap::new(),
+            troops: LinkedList::new(),
+            bombs: Vec::new(),
+            commands: Vec::new(),
+            bomb_count: 2,
+            bomb_last: -99,
+            troop_commands: LinkedList::new(),
+            rng: Rng::new(0),
+            nb_turn: 0,
+            bombing: false,
+            params: Params::new(),
+            budget: TimeBudget::start(0, 0),
+            territory: Territory::default(),
+            next_entity_id: 0,
+            last_seen: None,
+            profile: OpponentProfile::default(),
+            enemy_actions: EnemyActions::default(),
+            enemy_bomb_count: 2,
+            tracked_troops: BTreeMap::new()
+        }
+    }
+
+    pub fn init_factories_distance(&mut self, factory_count: i32) {
+        for i in 0..factory_count {
+            let mut distances: Vec<(i32, i32)> = Vec::new();
+            for (&(id1, id2), distance) in self.factory_distance.iter() {
+                if id1 == i {
+                    distances.push((*distance, id2));
+                } else if id2 == i {
+                    distances.push((*distance, id1));
+                }
+
+            }
+
+            distances.sort();
+            trace!(Category::Map, Level::Debug, "distances", "factory" => i,
+                   "distances" => distances.iter().map(|&(distance, id2)| vec![distance, id2]).collect::<Vec<_>>());
+
+            self.factories.insert(i, Factory{id: i, owner: -99, cyborg_count: -99, production: -99, distances: distances, disrupted: 0, cyborg_remaining: 0});
+        }
+    }
+    pub fn snapshot(&self) -> Snapshot {
+        return Snapshot{nb_turn: self.nb_turn, factories: self.factories.clone(), troops: self.troops.clone(), bombs: self.bombs.clone(), orders: Vec::new()};
+    }
+
+    pub fn new_entity_id(&mut self) -> i32 {
+        self.next_entity_id += 1;
+        return self.next_entity_id - 1;
+    }
+
+    pub fn distance(&self, id1: i32, id2: i32) -> i32 {
+        return *self.factory_distance.get(&(id1, id2))
+            .or_else(|| self.factory_distance.get(&(id2, id1)))
+            .unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+use std::env;
+use std::fs;
+
+use crate::eval::Evaluator;
+use crate::rng::Rng;
+
+// Tunable constants of the rule based AI, see `tune`
+#[derive(Clone, Copy)]
+pub struct Params {
+    pub inc_threshold: i32,
+    pub lookahead: i32, // Max simulated turns when predicting a capture
+    pub bomb_count: i32,
+    pub backline_garrison: i32, // Cyborgs kept by a backline factory, the surplus goes to the frontline
+    pub threat_horizon: i32, // Enemy garrisons within this distance are a threat even before they launch
+    pub max_orders: i32, // Orders per factory and per turn of allocate_moves, 0 turns it off
+    pub feint_value: f32, // Worth of a one cyborg troop to an enemy factory, a cyborg is worth owned_cyborgs
+    pub turn_budget_ms: i32, // Referee limit of a turn, the first turn has FIRST_TURN_BUDGET_MS
+    pub eval: Evaluator
+}
+
+impl Params {
+    pub fn new() -> Params {
+        Params {
+            inc_threshold: 15,
+            lookahead: 20,
+            bomb_count: 2,
+            backline_garrison: 5,
+            threat_horizon: 5,
+            max_orders: 3,
+            feint_value: 0.0,
+            turn_budget_ms: 50,
+            eval: Evaluator::new()
+        }
+    }
+
+    // Same format as the evaluator: GITC_PARAMS_FILE then GITC_PARAMS, evaluator weights are accepted too
+    pub fn load() -> Params {
+        let mut params = Params::new();
+        params.eval = Evaluator::load();
+
+        if let Ok(path) = env::var("GITC_PARAMS_FILE") {
+            match fs::read_to_string(&path) {
+                Ok(config) => params.parse(&config),
+                Err(err) => print_err!("[PARAMS] Cannot read {}: {}", path, err)
+            }
+        }
+
+        if let Ok(config) = env::var("GITC_PARAMS") {
+            params.parse(&config);
+        }
+
+        return params;
+    }
+
+    pub fn parse(&mut self, config: &str) {
+        for (key, value) in parse_config(config) {
+            if !self.set(&key, value) { print_err!("[PARAMS] Unknown param {}", key) }
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: f32) -> bool {
+        match key {
+            "inc_threshold" => self.inc_threshold = (value.round() as i32).max(10),
+            "lookahead" => self.lookahead = (value.round() as i32).max(1),
+            "bomb_count" => self.bomb_count = (value.round() as i32).clamp(0, 2),
+            "backline_garrison" => self.backline_garrison = (value.round() as i32).max(0),
+            "threat_horizon" => self.threat_horizon = (value.round() as i32).max(0),
+            "max_orders" => self.max_orders = (value.round() as i32).max(0),
+            "feint_value" => self.feint_value = value.max(0.0),
+            "turn_budget_ms" => self.turn_budget_ms = (value.round() as i32).max(1),
+            _ => return self.eval.set(key, value)
+        }
+        return true;
+    }
+
+    pub fn entries(&self) -> Vec<(&'static str, f32)> {
+        let mut entries = vec![
+            ("inc_threshold", self.inc_threshold as f32),
+            ("lookahead", self.lookahead as f32),
+            ("bomb_count", self.bomb_count as f32),
+            ("backline_garrison", self.backline_garrison as f32),
+            ("threat_horizon", self.threat_horizon as f32),
+            ("max_orders", self.max_orders as f32),
+            ("feint_value", self.feint_value)
+        ];
+        entries.extend(self.eval.entries());
+        return entries;
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut config = String::new();
+        for (key, value) in self.entries() {
+            config.push_str(&format!("{}={}\n", key, value));
+        }
+        config.push_str(&format!("turn_budget_ms={}\n", self.turn_budget_ms));
+        return config;
+    }
+
+    // Move one random param by 20% (or by 1 if it is zero)
+    pub fn mutate(&self, rng: &mut Rng) -> (Params, &'static str) {
+        let entries = self.entries();
+        let (key, value) = entries[rng.gen_range(0, entries.len() as i32) as usize];
+        let step = if value == 0.0 { 1.0 } else { (value * 0.2).abs().max(1.0) };
+
+        let mut params = *self;
+        params.set(key, if rng.gen_bool() { value + step } else { value - step });
+        return (params, key);
+    }
+}
+
+// "name=value" entries separated by commas or new lines, '#' starts a comment line
+pub fn parse_config(config: &str) -> Vec<(String, f32)> {
+    let mut entries = Vec::new();
+    for entry in config.split([',', '\n']) {
+        let entry = entry.trim();
+        if entry.is_empty() || entry.starts_with('#') { continue }
+
+        let mut parts = entry.splitn(2, '=');
+        let key = parts.next().unwrap().trim();
+        match parts.next().and_then(|value| value.trim().parse::<f32>().ok()) {
+            Some(value) => entries.push((key.to_string(), value)),
+            None => print_err!("Invalid config entry {}", entry)
+        }
+    }
+    return entries;
+}
@@ -0,0 +1,147 @@
+use std::rc::Rc;
+
+use crate::model::{GameState, HasOwner};
+use crate::trace::{Category, Level};
+
+// Turns where the targets of the opponent tell if it expands to the neutrals first
+const OPENING_TURNS: i32 = 5;
+
+// Habits of the opponent, counted from the differences between two consecutive turns
+#[derive(Clone, Default)]
+pub struct OpponentProfile {
+    pub turns: i32, // Turns observed
+    pub bombs: i32,
+    pub launches: i32, // New enemy troops
+    pub launched_share: f32, // Sum over the launches of the part of the source garrison sent
+    pub incs: i32,
+    pub neutral_launches: i32, // Launches toward a neutral factory
+    pub opening_launches: i32,
+    pub opening_neutral_launches: i32
+}
+
+impl OpponentProfile {
+    pub fn bomb_rate(&self) -> f32 {
+        return if self.turns > 0 { self.bombs as f32 / self.turns as f32 } else { 0.0 };
+    }
+
+    // Mean part of its garrison a factory sends in one troop, 1.0 is an all-in
+    pub fn attack_size(&self) -> f32 {
+        return if self.launches > 0 { self.launched_share / self.launches as f32 } else { 0.0 };
+    }
+
+    pub fn inc_rate(&self) -> f32 {
+        return if self.turns > 0 { self.incs as f32 / self.turns as f32 } else { 0.0 };
+    }
+
+    pub fn neutral_share(&self) -> f32 {
+        return if self.launches > 0 { self.neutral_launches as f32 / self.launches as f32 } else { 0.0 };
+    }
+
+    // Most of the opening launches went to the neutrals
+    pub fn neutrals_first(&self) -> bool {
+        return self.opening_launches > 0 && 2 * self.opening_neutral_launches > self.opening_launches;
+    }
+}
+
+impl GameState {
+    // Compare the turn read from the referee with the previous one, then keep it for the next turn
+    pub fn update_profile(&mut self) {
+        let last = match self.last_seen.take() {
+            Some(last) => last,
+            None => {
+                self.last_seen = Some(Rc::new(self.snapshot()));
+                return;
+            }
+        };
+        self.update_enemy_actions(&last);
+        self.check_simulation(&last);
+
+        let profile = &mut self.profile;
+        profile.turns += 1;
+        profile.bombs += self.enemy_actions.bombs.len() as i32;
+        profile.incs += self.enemy_actions.incs.len() as i32;
+        for &(from, to, count) in self.enemy_actions.moves.iter() {
+            let source = last.factories.get(&from).unwrap();
+            let target = last.factories.get(&to).unwrap();
+
+            profile.launches += 1;
+            if source.cyborg_count > 0 {
+                profile.launched_share += (count as f32 / source.cyborg_count as f32).min(1.0);
+            }
+            if target.is_neutral() { profile.neutral_launches += 1 }
+            if self.nb_turn <= OPENING_TURNS {
+                profile.opening_launches += 1;
+                if target.is_neutral() { profile.opening_neutral_launches += 1 }
+            }
+        }
+
+        trace!(Category::Opponent, Level::Debug, "profile", "turns" => profile.turns, "bomb_rate" => profile.bomb_rate(),
+               "attack_size" => profile.attack_size(), "inc_rate" => profile.inc_rate(), "neutral_share" => profile.neutral_share(),
+               "neutrals_first" => profile.neutrals_first());
+        self.last_seen = Some(Rc::new(self.snapshot()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Bomb;
+    use crate::testing::{new_map, troop};
+
+    // We are 0, the enemy is 1 and 2 is neutral
+    fn state() -> GameState {
+        let mut state = new_map(&[(0, 1, 20, 1), (1, -1, 20, 1), (2, 0, 5, 2)], &[(0, 1, 6), (0, 2, 3), (1, 2, 3)]);
+        state.update_profile();
+        return state;
+    }
+
+    fn launch(state: &mut GameState, id: i32, to: i32, count: i32) {
+        let mut troop = troop(-1, 1, to, count, state.distance(1, to));
+        troop.id = id;
+        state.troops.push_back(troop);
+        state.factories.get_mut(&1).unwrap().cyborg_count -= count;
+    }
+
+    #[test]
+    fn new_enemy_troops_are_launches() {
+        let mut state = state();
+        state.nb_turn = 1;
+        launch(&mut state, 10, 2, 6);
+        launch(&mut state, 11, 0, 4);
+        state.update_profile();
+
+        assert_eq!(state.profile.launches, 2);
+        assert_eq!(state.profile.attack_size(), 0.25);
+        assert_eq!(state.profile.neutral_share(), 0.5);
+        assert!(!state.profile.neutrals_first());
+
+        // The same troops one turn later are not new
+        state.nb_turn = 2;
+        for troop in state.troops.iter_mut() {
+            troop.turn_remaining -= 1;
+        }
+        launch(&mut state, 12, 2, 10);
+        state.update_profile();
+
+        assert_eq!(state.profile.launches, 3);
+        assert!(state.profile.neutrals_first());
+    }
+
+    #[test]
+    fn bombs_and_incs_are_counted() {
+        let mut state = state();
+        state.nb_turn = 1;
+        state.bombs.push(Bomb{id: 7, owner: -1, factory_start: 1, factory_end: 0, turn_remaining: 6});
+        let factory = state.factories.get_mut(&1).unwrap();
+        factory.production += 1;
+        factory.cyborg_count -= 10;
+        state.update_profile();
+
+        state.nb_turn = 2;
+        state.update_profile();
+
+        assert_eq!((state.profile.bombs, state.profile.incs, state.profile.turns), (1, 1, 2));
+        assert_eq!(state.profile.bomb_rate(), 0.5);
+        assert_eq!(state.profile.launches, 0);
+    }
+}
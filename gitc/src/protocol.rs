@@ -0,0 +1,142 @@
+use std::io;
+use std::env;
+use std::fs;
+use std::collections::VecDeque;
+
+use crate::model::{Bomb, GameState, Troop};
+use crate::time::TimeBudget;
+use crate::trace::{Category, Level};
+
+// Referee lines from stdin or from a replay, optionally recorded to the GITC_REPLAY file
+// Replay format: "P <params>" and "S <seed>" once, then "I <input line>", "O <output line>" and "T <elapsed ms>" for each turn
+pub struct Input {
+    replay: Option<VecDeque<String>>,
+    recorder: Option<fs::File>
+}
+
+impl Input {
+    pub fn stdin() -> Input {
+        let recorder = env::var("GITC_REPLAY").ok().and_then(|path| match fs::File::create(&path) {
+            Ok(file) => Some(file),
+            Err(err) => { print_err!("Cannot record replay {}: {}", path, err); None }
+        });
+        return Input{replay: None, recorder: recorder};
+    }
+
+    pub fn replay(lines: VecDeque<String>) -> Input {
+        return Input{replay: Some(lines), recorder: None};
+    }
+
+    pub fn read_line(&mut self) -> String {
+        let line = match self.replay {
+            Some(ref mut lines) => lines.pop_front().expect("Replay has no more input"),
+            None => {
+                let mut input_line = String::new();
+                io::stdin().read_line(&mut input_line).unwrap();
+                input_line.trim_end_matches(['\n', '\r']).to_string()
+            }
+        };
+        self.record('I', &line);
+        return line;
+    }
+
+    pub fn record(&mut self, tag: char, line: &str) {
+        use std::io::Write;
+        if let Some(ref mut file) = self.recorder {
+            writeln!(file, "{} {}", tag, line).ok();
+        }
+    }
+}
+
+impl GameState {
+    pub fn init_links(&mut self, input: &mut Input) {
+        let input_line = input.read_line();
+        let factory_count = parse_input!(input_line, i32); // the number of factories
+        let input_line = input.read_line();
+        let link_count = parse_input!(input_line, i32); // the number of links between factories
+        for _ in 0..link_count as usize {
+            let input_line = input.read_line();
+            let inputs = input_line.split(" ").collect::<Vec<_>>();
+            let factory_1 = parse_input!(inputs[0], i32);
+            let factory_2 = parse_input!(inputs[1], i32);
+            let distance = parse_input!(inputs[2], i32);
+
+            trace!(Category::Map, Level::Debug, "link", "factory_1" => factory_1, "factory_2" => factory_2, "distance" => distance);
+            self.factory_distance.insert((factory_1, factory_2), distance);
+        }
+        self.init_factories_distance(factory_count);
+    }
+
+    pub fn init_entities(&mut self, input: &mut Input) {
+        self.troops.clear();
+        self.bombs.clear();
+        self.bombing = false;
+
+        let input_line = input.read_line();
+        let entity_count = parse_input!(input_line, i32); // the number of entities (e.g. factories and troops)
+        for _ in 0..entity_count as usize {
+            let input_line = input.read_line();
+            let inputs = input_line.split(" ").collect::<Vec<_>>();
+            let entity_id = parse_input!(inputs[0], i32);
+            let entity_type = inputs[1].trim().to_string();
+            let arg_1 = parse_input!(inputs[2], i32);
+            let arg_2 = parse_input!(inputs[3], i32);
+            let arg_3 = parse_input!(inputs[4], i32);
+            let arg_4 = parse_input!(inputs[5], i32);
+            let arg_5 = parse_input!(inputs[6], i32);
+
+            if entity_type == "FACTORY" {
+                let factory = self.factories.get_mut(&entity_id).unwrap();
+                factory.owner = arg_1;
+                factory.cyborg_count = arg_2;
+                factory.production = arg_3;
+                factory.disrupted = arg_4;
+                factory.cyborg_remaining = factory.cyborg_count;
+            } else if entity_type == "TROOP" {
+                self.next_entity_id = self.next_entity_id.max(entity_id + 1);
+                self.troops.push_back(Troop{id: entity_id, owner: arg_1, factory_start: arg_2, factory_end: arg_3, cyborg_count: arg_4, turn_remaining: arg_5});
+            } else if entity_type == "BOMB" {
+                if arg_1 == -1 { self.bombing = true }
+                self.next_entity_id = self.next_entity_id.max(entity_id + 1);
+                self.bombs.push(Bomb{id: entity_id, owner: arg_1, factory_start: arg_2, factory_end: arg_3, turn_remaining: arg_4});
+            }
+
+        }
+
+        self.budget = TimeBudget::start(self.nb_turn, self.params.turn_budget_ms);
+    }
+
+    // A command read back from a replay or a previous turn, `side` owns the MOVE
+    pub fn push_order(&mut self, side: i32, order: &str) {
+        let words: Vec<&str> = order.split_whitespace().collect();
+        if words.first() == Some(&"MOVE") && words.len() == 4 {
+            self.troop_commands.push_back(Troop{id: 999, owner: side, factory_start: parse_input!(words[1], i32), factory_end: parse_input!(words[2], i32),
+                                                cyborg_count: parse_input!(words[3], i32), turn_remaining: 0});
+        } else {
+            self.commands.push(order.to_string());
+        }
+    }
+
+    pub fn print_commands(&mut self) {
+        println!("{}", self.take_commands());
+    }
+
+    pub fn take_commands(&mut self) -> String {
+
+        let mut final_command = "MSG El Psy Congroo".to_string();
+        for command in self.commands.iter() {
+            final_command.push(';');
+            final_command.push_str(command);
+        }
+
+        for troop in self.troop_commands.iter() {
+            final_command.push(';');
+            final_command.push_str(&format!("MOVE {} {} {}", troop.factory_start, troop.factory_end, troop.cyborg_count));
+        }
+
+        self.troop_commands.clear();
+        self.commands.clear();
+
+        return final_command;
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::VecDeque;
+use std::fs;
+
+use crate::model::GameState;
+use crate::params::Params;
+use crate::protocol::Input;
+use crate::rng::Rng;
+use crate::strategy::Brain;
+
+// Feed a GITC_REPLAY recording back to the rule based AI and diff its commands: `cargo run --release -p bot -- replay <file>`
+pub fn replay(args: &[String], seed: Option<u64>) {
+    let path = match args.first() {
+        Some(path) => path,
+        None => { print_err!("Usage: cargo run --release -p bot -- replay <file>"); return }
+    };
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => { print_err!("Cannot read {}: {}", path, err); return }
+    };
+
+    let mut params = Params::new();
+    let mut recorded_seed = 0;
+    let mut inputs = VecDeque::new();
+    let mut outputs: Vec<(String, String)> = Vec::new(); // (commands, elapsed ms)
+    for line in content.lines() {
+        let (tag, value) = line.split_at(line.find(' ').unwrap_or(line.len()));
+        let value = value.trim_start_matches(' ').to_string();
+        match tag {
+            "P" => params.parse(&value),
+            "S" => recorded_seed = parse_input!(value, u64),
+            "I" => inputs.push_back(value),
+            "O" => outputs.push((value, "?".to_string())),
+            "T" => if let Some(output) = outputs.last_mut() { output.1 = value },
+            _ => print_err!("Unknown replay line {}", line)
+        }
+    }
+
+    let mut input = Input::replay(inputs);
+    let mut game_state: GameState = GameState::new();
+    game_state.rng = Rng::new(seed.unwrap_or(recorded_seed));
+    game_state.params = params;
+    game_state.bomb_count = params.bomb_count;
+    game_state.init_links(&mut input);
+
+    let mut mismatches = 0;
+    for (turn, (recorded, elapsed)) in outputs.iter().enumerate() {
+        game_state.init_entities(&mut input);
+        game_state.play_turn(Brain::Rules);
+        let output = game_state.take_commands();
+        game_state.nb_turn += 1;
+
+        if output == *recorded { continue }
+        mismatches += 1;
+
+        let recorded_commands: Vec<&str> = recorded.split(';').collect();
+        let output_commands: Vec<&str> = output.split(';').collect();
+        println!("Turn {} (recorded in {} ms)", turn, elapsed);
+        for command in recorded_commands.iter().filter(|command| !output_commands.contains(command)) {
+            println!("- {}", command);
+        }
+        for command in output_commands.iter().filter(|command| !recorded_commands.contains(command)) {
+            println!("+ {}", command);
+        }
+        if recorded_commands.len() == output_commands.len() && recorded_commands.iter().all(|command| output_commands.contains(command)) {
+            println!("  Same commands in another order");
+        }
+    }
+
+    println!("{} turns replayed, {} different", outputs.len(), mismatches);
+}
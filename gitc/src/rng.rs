@@ -0,0 +1,55 @@
+use std::env;
+use std::time::Duration;
+
+// xorshift64*, seeded so that random searches and local games can be replayed
+#[derive(Clone)]
+pub struct Rng {
+    pub state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // splitmix64 so that close seeds give unrelated sequences, the state must not be 0
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        return Rng{state: (z ^ (z >> 31)).max(1)};
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return self.state.wrapping_mul(0x2545F4914F6CDD1D);
+    }
+
+    // In [low, high)
+    pub fn gen_range(&mut self, low: i32, high: i32) -> i32 {
+        return low + ((self.next_u64() >> 32) % (high - low) as u64) as i32;
+    }
+
+    pub fn gen_bool(&mut self) -> bool {
+        return self.next_u64() >> 63 == 1;
+    }
+
+    // Independent generator for a cloned state
+    pub fn fork(&mut self) -> Rng {
+        return Rng::new(self.next_u64());
+    }
+}
+
+// --seed N, then GITC_SEED, else the clock. The seed is always logged so that the run can be replayed
+pub fn startup_seed(args: &mut Vec<String>) -> u64 {
+    let mut seed = env::var("GITC_SEED").ok().and_then(|seed| seed.trim().parse::<u64>().ok());
+    if let Some(position) = args.iter().position(|arg| arg == "--seed") {
+        seed = args.get(position + 1).and_then(|seed| seed.parse::<u64>().ok());
+        args.drain(position..(position + 2).min(args.len()));
+    }
+
+    let seed = seed.unwrap_or_else(|| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+        now.as_secs() ^ (now.subsec_nanos() as u64) << 20
+    });
+    print_err!("Seed: {}", seed);
+    return seed;
+}
@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use std::fs;
+use std::thread;
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::model::{GameState, HasOwner, Troop};
+use crate::params::Params;
+use crate::rng::Rng;
+use crate::strategy::Brain;
+use crate::time::TimeBudget;
+
+impl GameState {
+    // Load the referee state of a local game as seen by `side`
+    pub fn observe(&mut self, referee: &GameState, side: i32) {
+        self.troops.clear();
+        self.bombs.clear();
+        self.bombing = false;
+
+        for (id, factory) in self.factories.iter_mut() {
+            let real = referee.factories.get(id).unwrap();
+            factory.owner = real.owner * side;
+            factory.cyborg_count = real.cyborg_count;
+            factory.production = real.production;
+            factory.disrupted = real.disrupted;
+            factory.cyborg_remaining = factory.cyborg_count;
+        }
+
+        for troop in referee.troops.iter() {
+            let mut troop = troop.clone();
+            troop.owner *= side;
+            self.troops.push_back(troop);
+        }
+
+        self.next_entity_id = referee.next_entity_id;
+        for bomb in referee.bombs.iter() {
+            let mut bomb = bomb.clone();
+            bomb.owner *= side;
+            if bomb.is_enemy() {
+                self.bombing = true;
+                bomb.factory_end = -1;
+                bomb.turn_remaining = -1;
+            }
+            self.bombs.push(bomb);
+        }
+
+        self.budget = TimeBudget::start(self.nb_turn, self.params.turn_budget_ms);
+    }
+}
+
+// Approximation of the referee map generator: factory 0 in the middle, the others mirrored by pair
+pub fn generate_map(seed: u64) -> GameState {
+    let mut rng = Rng::new(seed);
+    let (width, height) = (16000, 6500);
+    let factory_count = 7 + 2 * rng.gen_range(0, 5);
+
+    let mut positions: Vec<(i32, i32)> = vec![(width / 2, height / 2)];
+    while positions.len() < factory_count as usize {
+        let (x, y) = (rng.gen_range(700, width - 700), rng.gen_range(700, height - 700));
+        let mirror = (width - x, height - y);
+        let far_enough = positions.iter().chain([mirror].iter())
+            .all(|&(x2, y2)| (((x - x2).pow(2) + (y - y2).pow(2)) as f32).sqrt() > 1900.0);
+        if far_enough {
+            positions.push((x, y));
+            positions.push(mirror);
+        }
+    }
+
+    let mut map = GameState::new();
+    for i in 0..factory_count {
+        for j in (i + 1)..factory_count {
+            let (x1, y1) = positions[i as usize];
+            let (x2, y2) = positions[j as usize];
+            let length = (((x1 - x2).pow(2) + (y1 - y2).pow(2)) as f32).sqrt();
+            map.factory_distance.insert((i, j), (((length - 1400.0) / 800.0).ceil() as i32).max(1));
+        }
+    }
+    map.init_factories_distance(factory_count);
+
+    for i in 0..factory_count {
+        let factory = map.factories.get_mut(&i).unwrap();
+        factory.owner = 0;
+        factory.production = if i == 0 { 0 } else { rng.gen_range(0, 4) };
+        factory.cyborg_count = if i == 0 { 0 } else { rng.gen_range(0, 6 * factory.production + 1) };
+    }
+    // Second factory of each pair is a copy of the first one
+    for i in (2..factory_count).step_by(2) {
+        let first = map.factories.get(&(i - 1)).unwrap().clone();
+        let second = map.factories.get_mut(&i).unwrap();
+        second.production = first.production;
+        second.cyborg_count = first.cyborg_count;
+    }
+
+    let start_cyborgs = rng.gen_range(15, 31);
+    map.rng = rng;
+    for &(id, owner) in [(1, 1), (2, -1)].iter() {
+        let factory = map.factories.get_mut(&id).unwrap();
+        factory.owner = owner;
+        factory.cyborg_count = start_cyborgs;
+        factory.production = 1.max(factory.production);
+    }
+
+    return map;
+}
+
+// A named AI of local games
+#[derive(Clone)]
+pub struct Variant {
+    pub name: String,
+    pub brain: Brain,
+    pub params: Params
+}
+
+impl Variant {
+    // "name=rules", "name=random" or "name=rules:params.txt"
+    pub fn parse(spec: &str) -> Option<Variant> {
+        let mut parts = spec.splitn(2, '=');
+        let name = parts.next().unwrap().to_string();
+        let mut parts = parts.next()?.splitn(2, ':');
+        let brain = match parts.next()? {
+            "rules" => Brain::Rules,
+            "random" => Brain::RandomSearch,
+            _ => return None
+        };
+
+        let mut params = Params::load();
+        if let Some(path) = parts.next() {
+            match fs::read_to_string(path) {
+                Ok(config) => params.parse(&config),
+                Err(err) => { print_err!("Cannot read {}: {}", path, err); return None }
+            }
+        }
+
+        return Some(Variant{name: name, brain: brain, params: params});
+    }
+}
+
+pub fn new_bot(map: &GameState, params: &Params, side: i32) -> GameState {
+    let mut bot = GameState::new();
+    bot.rng = Rng::new(map.rng.clone().next_u64() ^ side as u64);
+    bot.params = *params;
+    bot.bomb_count = params.bomb_count;
+    bot.factory_distance = map.factory_distance.clone();
+    bot.init_factories_distance(map.factories.len() as i32);
+    return bot;
+}
+
+// Hand the bot orders to the referee, like the real referee a troop can't take more than the source garrison
+pub fn issue_orders(referee: &mut GameState, bot: &mut GameState, side: i32) {
+    let mut garrisons: HashMap<i32, i32> = referee.factories.values()
+        .filter(|fac| fac.owner == side)
+        .map(|fac| (fac.id, fac.cyborg_count))
+        .collect();
+
+    for troop in bot.troop_commands.iter() {
+        if troop.factory_start == troop.factory_end { continue }
+        let available = match garrisons.get_mut(&troop.factory_start) {
+            Some(available) => available,
+            None => continue
+        };
+
+        let cyborg_count = troop.cyborg_count.min(*available);
+        if cyborg_count <= 0 { continue }
+        *available -= cyborg_count;
+
+        let distance = referee.distance(troop.factory_start, troop.factory_end);
+        referee.troop_commands.push_back(Troop{id: 999, owner: side, factory_start: troop.factory_start, factory_end: troop.factory_end, cyborg_count: cyborg_count, turn_remaining: distance});
+    }
+
+    // INC and BOMB belong to the owner of their source factory
+    for command in bot.commands.iter() {
+        let source = command.split_whitespace().nth(1).and_then(|id| id.parse::<i32>().ok());
+        if let Some(id) = source {
+            if garrisons.contains_key(&id) { referee.commands.push(command.clone()) }
+        }
+    }
+
+    bot.troop_commands.clear();
+    bot.commands.clear();
+}
+
+// Winner of a local game: 1, -1 or 0 for a draw
+pub fn play_game(map: &GameState, player: &Variant, enemy: &Variant) -> i32 {
+    let mut referee = map.clone();
+    let mut bots = [(new_bot(map, &player.params, 1), player.brain, 1), (new_bot(map, &enemy.params, -1), enemy.brain, -1)];
+
+    while !referee.is_terminal() {
+        for &mut (ref mut bot, brain, side) in bots.iter_mut() {
+            bot.observe(&referee, side);
+            bot.play_turn(brain);
+            issue_orders(&mut referee, bot, side);
+            bot.nb_turn += 1;
+        }
+        referee.sim_next_turn();
+    }
+
+    return referee.winner();
+}
+
+pub struct MatchResult {
+    pub wins: i32,
+    pub draws: i32,
+    pub losses: i32
+}
+
+impl MatchResult {
+    pub fn add(&mut self, winner: i32) {
+        match winner {
+            1 => self.wins += 1,
+            -1 => self.losses += 1,
+            _ => self.draws += 1
+        }
+    }
+
+    pub fn games(&self) -> i32 {
+        return self.wins + self.draws + self.losses;
+    }
+
+    // Draws count as half a win
+    pub fn win_rate(&self) -> f32 {
+        return (self.wins as f32 + self.draws as f32 * 0.5) / self.games().max(1) as f32;
+    }
+
+    // Half width of the 95% confidence interval (normal approximation)
+    pub fn confidence(&self) -> f32 {
+        let p = self.win_rate();
+        return 1.96 * (p * (1.0 - p) / self.games().max(1) as f32).sqrt();
+    }
+}
+
+// Each map is played twice, one time per seat
+pub fn play_match(player: &Variant, enemy: &Variant, maps: i32, seed: u64) -> MatchResult {
+    let mut result = MatchResult{wins: 0, draws: 0, losses: 0};
+    for i in 0..maps {
+        let map = generate_map(seed + i as u64);
+        for &winner in [play_game(&map, player, enemy), -play_game(&map, enemy, player)].iter() {
+            result.add(winner);
+        }
+    }
+    return result;
+}
+
+// Hill climbing by self-play: `cargo run --release -p bot -- tune [iterations] [maps per match] [output file]`
+pub fn tune(args: &[String], seed: u64) {
+    let iterations = args.first().map_or(50, |arg| parse_input!(arg, i32));
+    let maps = args.get(1).map_or(10, |arg| parse_input!(arg, i32));
+    let output = args.get(2).map_or("params.txt".to_string(), |arg| arg.clone());
+
+    let mut rng = Rng::new(seed);
+    let mut best = Params::load();
+    for iteration in 0..iterations {
+        let (candidate, key) = best.mutate(&mut rng);
+        let result = play_match(&Variant{name: "candidate".to_string(), brain: Brain::Rules, params: candidate},
+                                &Variant{name: "best".to_string(), brain: Brain::Rules, params: best},
+                                maps, iteration as u64 * maps as u64);
+        let value = candidate.entries().iter().find(|entry| entry.0 == key).unwrap().1;
+        println!("#{} {}={} win rate {:.1}% +/- {:.1}% ({}W {}D {}L)", iteration, key, value,
+                 result.win_rate() * 100.0, result.confidence() * 100.0, result.wins, result.draws, result.losses);
+
+        if result.win_rate() > 0.5 {
+            best = candidate;
+            match fs::write(&output, best.to_config()) {
+                Ok(_) => println!("New best written to {}", output),
+                Err(err) => print_err!("Cannot write {}: {}", output, err)
+            }
+        }
+    }
+
+    println!("{}", best.to_config());
+}
+
+// Outcome of the two games of a map between variants first and second, from the first one point of view
+struct Pairing {
+    first: usize,
+    second: usize,
+    map: i32,
+    winners: [i32; 2]
+}
+
+// Round robin on seeded maps in both seats: `cargo run --release -p bot -- tournament [maps] [threads] [name=rules|random[:params file]]...`
+pub fn tournament(args: &[String]) {
+    let maps = args.first().map_or(10, |arg| parse_input!(arg, i32));
+    let threads = args.get(1).map_or(thread::available_parallelism().map_or(1, |count| count.get()), |arg| parse_input!(arg, usize));
+    let mut variants: Vec<Variant> = args.iter().skip(2).filter_map(|spec| {
+        let variant = Variant::parse(spec);
+        if variant.is_none() { print_err!("Invalid variant {}", spec) }
+        variant
+    }).collect();
+    if variants.is_empty() {
+        variants = vec![Variant::parse("rules=rules").unwrap(), Variant::parse("random=random").unwrap()];
+    }
+
+    let mut jobs = Vec::new();
+    for first in 0..variants.len() {
+        for second in (first + 1)..variants.len() {
+            for map in 0..maps {
+                jobs.push((first, second, map));
+            }
+        }
+    }
+
+    let variants = Arc::new(variants);
+    let jobs = Arc::new(Mutex::new(jobs));
+    let (sender, receiver) = mpsc::channel();
+    let mut workers = Vec::new();
+    for _ in 0..threads.max(1) {
+        let (variants, jobs, sender) = (variants.clone(), jobs.clone(), sender.clone());
+        workers.push(thread::spawn(move || {
+            loop {
+                let job = jobs.lock().unwrap().pop();
+                let (first, second, map) = match job { Some(job) => job, None => break };
+                let game_map = generate_map(map as u64);
+                let winners = [play_game(&game_map, &variants[first], &variants[second]),
+                               -play_game(&game_map, &variants[second], &variants[first])];
+                sender.send(Pairing{first: first, second: second, map: map, winners: winners}).unwrap();
+            }
+        }));
+    }
+    drop(sender);
+
+    let mut pairings: Vec<Pairing> = receiver.iter().collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    pairings.sort_by_key(|pairing| (pairing.map, pairing.first, pairing.second));
+
+    // Elo updated game after game in a fixed order
+    let mut results: Vec<MatchResult> = variants.iter().map(|_| MatchResult{wins: 0, draws: 0, losses: 0}).collect();
+    let mut elo: Vec<f32> = vec![1500.0; variants.len()];
+    let mut map_points: Vec<Vec<f32>> = vec![vec![0.0; variants.len()]; maps as usize];
+    for pairing in pairings.iter() {
+        for &winner in pairing.winners.iter() {
+            results[pairing.first].add(winner);
+            results[pairing.second].add(-winner);
+
+            let score = (winner as f32 + 1.0) / 2.0;
+            let expected = 1.0 / (1.0 + 10f32.powf((elo[pairing.second] - elo[pairing.first]) / 400.0));
+            elo[pairing.first] += 16.0 * (score - expected);
+            elo[pairing.second] -= 16.0 * (score - expected);
+
+            map_points[pairing.map as usize][pairing.first] += score;
+            map_points[pairing.map as usize][pairing.second] += 1.0 - score;
+        }
+    }
+
+    println!("{:<16} {:>5} {:>5} {:>5} {:>7}", "Variant", "W", "D", "L", "Elo");
+    for (i, variant) in variants.iter().enumerate() {
+        println!("{:<16} {:>5} {:>5} {:>5} {:>7.0}", variant.name, results[i].wins, results[i].draws, results[i].losses, elo[i]);
+    }
+
+    println!();
+    for first in 0..variants.len() {
+        for second in (first + 1)..variants.len() {
+            let mut result = MatchResult{wins: 0, draws: 0, losses: 0};
+            for pairing in pairings.iter().filter(|pairing| pairing.first == first && pairing.second == second) {
+                result.add(pairing.winners[0]);
+                result.add(pairing.winners[1]);
+            }
+            println!("{} vs {}: {}W {}D {}L ({:.1}% +/- {:.1}%)", variants[first].name, variants[second].name,
+                     result.wins, result.draws, result.losses, result.win_rate() * 100.0, result.confidence() * 100.0);
+        }
+    }
+
+    println!();
+    for (map, points) in map_points.iter().enumerate() {
+        let line: Vec<String> = variants.iter().enumerate().map(|(i, variant)| format!("{} {}", variant.name, points[i])).collect();
+        println!("Map {} ({} factories): {}", map, generate_map(map as u64).factories.len(), line.join(", "));
+    }
+}
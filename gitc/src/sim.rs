@@ -0,0 +1,474 @@
+use std::collections::BTreeMap;
+use std::collections::LinkedList;
+
+use crate::model::{Bomb, Factory, GameState, HasOwner, Troop, BOMB_DISRUPTION, INC_COST, MAX_PRODUCTION, MAX_TURNS};
+
+impl Factory {
+    // INC is ignored by the referee without enough cyborgs or at max production
+    pub fn increase_production(&mut self) {
+        if self.cyborg_count < INC_COST || self.production >= MAX_PRODUCTION { return }
+        self.cyborg_count -= INC_COST;
+        self.production += 1;
+    }
+
+    pub fn produce(&mut self) {
+        if self.disrupted > 0 {
+            self.disrupted -= 1;
+        } else if !self.is_neutral() {
+            self.cyborg_count += self.production;
+        }
+    }
+
+    // Half of the garrison with a minimum of 10 cyborgs, then no production for 5 turns
+    pub fn explode_bomb(&mut self) {
+        self.cyborg_count -= self.cyborg_count.min((self.cyborg_count / 2).max(10));
+        self.disrupted = BOMB_DISRUPTION;
+    }
+
+    // Troops arriving the same turn fight each other first, the survivors reinforce or fight the garrison
+    pub fn resolve_arrivals(&mut self, player_count: i32, enemy_count: i32) {
+        let (side, count) = if player_count > enemy_count {
+            (1, player_count - enemy_count)
+        } else {
+            (-1, enemy_count - player_count)
+        };
+        if count == 0 { return }
+
+        if self.owner == side {
+            self.cyborg_count += count;
+        } else if count > self.cyborg_count {
+            self.owner = side;
+            self.cyborg_count = count - self.cyborg_count;
+        } else {
+            // On a tie the factory keeps its owner with an empty garrison
+            self.cyborg_count -= count;
+        }
+    }
+}
+
+impl GameState {
+    pub fn sim_next_turn(&mut self) {
+        // Troops Moving, the arrivals only fight after the production
+        let mut arrivals: BTreeMap<i32, (i32, i32)> = BTreeMap::new(); // factory -> (player cyborgs, enemy cyborgs)
+        for troop in self.troops.iter_mut() {
+            troop.turn_remaining -= 1;
+            if troop.turn_remaining == 0 {
+                let arrival = arrivals.entry(troop.factory_end).or_insert((0, 0));
+                if troop.is_player() {
+                    arrival.0 += troop.cyborg_count;
+                } else {
+                    arrival.1 += troop.cyborg_count;
+                }
+            }
+        }
+        self.troops = self.troops.iter()
+            .filter(|troop| troop.turn_remaining > 0)
+            .cloned()
+            .collect();
+
+        // Bombs Moving, the enemy bombs are ignored as their target is unknown
+        for bomb in self.bombs.iter_mut() {
+            if bomb.turn_remaining > 0 {
+                bomb.turn_remaining -= 1;
+            }
+        }
+
+        // Orders Execution: bombs, moves then INC. The owner of an order is the owner of its source factory
+        let commands: Vec<String> = self.commands.drain(..).collect();
+        let mut incs = Vec::new();
+        let mut troop_commands = LinkedList::new();
+        troop_commands.append(&mut self.troop_commands);
+        for command in commands.iter() {
+            let words: Vec<&str> = command.split_whitespace().collect();
+            let ids: Vec<i32> = words.iter().skip(1).filter_map(|word| word.parse::<i32>().ok()).collect();
+            let owner = match ids.first().and_then(|id| self.factories.get(id)) {
+                Some(factory) if !factory.is_neutral() => factory.owner,
+                _ => continue
+            };
+
+            match (words[0], ids.len()) {
+                ("BOMB", 2) => {
+                    let distance = self.distance(ids[0], ids[1]);
+                    let id = self.new_entity_id();
+                    self.bombs.push(Bomb{id: id, owner: owner, factory_start: ids[0], factory_end: ids[1], turn_remaining: distance});
+                },
+                ("MOVE", 3) => troop_commands.push_back(Troop{id: 999, owner: owner, factory_start: ids[0], factory_end: ids[1], cyborg_count: ids[2], turn_remaining: 0}),
+                ("INC", 1) => incs.push(ids[0]),
+                _ => {}
+            }
+        }
+
+        // A troop can't take more than the garrison present before the arrivals
+        for mut troop in troop_commands.into_iter() {
+            if troop.factory_start == troop.factory_end { continue }
+            troop.turn_remaining = self.distance(troop.factory_start, troop.factory_end);
+            let factory = self.factories.get_mut(&troop.factory_start).unwrap();
+            if factory.owner != troop.owner { continue }
+            troop.cyborg_count = troop.cyborg_count.min(factory.cyborg_count);
+            if troop.cyborg_count <= 0 { continue }
+
+            factory.cyborg_count -= troop.cyborg_count;
+            troop.id = self.new_entity_id();
+            self.troops.push_back(troop);
+        }
+
+        for id in incs.iter() {
+            self.factories.get_mut(id).unwrap().increase_production();
+        }
+
+        // Production
+        for factory in self.factories.values_mut() {
+            factory.produce();
+        }
+
+        // Combat
+        for (id, &(player_count, enemy_count)) in arrivals.iter() {
+            self.factories.get_mut(id).unwrap().resolve_arrivals(player_count, enemy_count);
+        }
+
+        // Bombs
+        for bomb in self.bombs.iter() {
+            if bomb.turn_remaining == 0 {
+                self.factories.get_mut(&bomb.factory_end).unwrap().explode_bomb();
+            }
+        }
+        self.bombs.retain(|bomb| bomb.turn_remaining != 0);
+
+        self.nb_turn += 1;
+    }
+
+    // Score of a side at the end of the game
+    pub fn total_cyborgs(&self, side: i32) -> i32 {
+        let garrisons: i32 = self.factories.values().filter(|fac| fac.owner == side).map(|fac| fac.cyborg_count).sum();
+        let troops: i32 = self.troops.iter().filter(|troop| troop.owner == side).map(|troop| troop.cyborg_count).sum();
+        return garrisons + troops;
+    }
+
+    // No cyborg left anywhere and no factory to produce more
+    pub fn is_eliminated(&self, side: i32) -> bool {
+        return self.total_cyborgs(side) == 0
+            && !self.factories.values().any(|fac| fac.owner == side && fac.production > 0);
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        return self.nb_turn >= MAX_TURNS || self.is_eliminated(1) || self.is_eliminated(-1);
+    }
+
+    // 1, -1 or 0 for a draw, only meaningful once the state is terminal
+    pub fn winner(&self) -> i32 {
+        match (self.is_eliminated(1), self.is_eliminated(-1)) {
+            (true, false) => return -1,
+            (false, true) => return 1,
+            _ => return (self.total_cyborgs(1) - self.total_cyborgs(-1)).signum()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+    use crate::testing::{factory, new_state, troop};
+
+    #[test]
+    fn neutral_factory_is_captured_by_a_bigger_troop() {
+        let mut state = new_state(&[(0, 0, 6, 2), (1, 1, 0, 0)], 2);
+        state.troops.push_back(troop(1, 1, 0, 9, 1));
+        state.sim_next_turn();
+
+        // Neutral factories don't produce, the capture happens after the production
+        assert_eq!(factory(&state, 0), (1, 3));
+        state.sim_next_turn();
+        assert_eq!(factory(&state, 0), (1, 5));
+    }
+
+    #[test]
+    fn neutral_factory_resists_a_smaller_troop() {
+        let mut state = new_state(&[(0, 0, 6, 2), (1, 1, 0, 0)], 2);
+        state.troops.push_back(troop(1, 1, 0, 6, 1));
+        state.sim_next_turn();
+
+        assert_eq!(factory(&state, 0), (0, 0));
+    }
+
+    #[test]
+    fn reinforcement_joins_the_garrison() {
+        let mut state = new_state(&[(0, 1, 4, 1), (1, 1, 0, 0)], 2);
+        state.troops.push_back(troop(1, 1, 0, 7, 1));
+        state.sim_next_turn();
+
+        assert_eq!(factory(&state, 0), (1, 12));
+    }
+
+    #[test]
+    fn troop_arrives_after_the_link_distance() {
+        let mut state = new_state(&[(0, 1, 10, 0), (1, -1, 3, 0)], 4);
+        state.troop_commands.push_back(troop(1, 0, 1, 10, 999));
+        for turn in 0..4 {
+            state.sim_next_turn();
+            assert_eq!(factory(&state, 1), (-1, 3), "turn {}", turn);
+            assert_eq!(state.troops.front().unwrap().turn_remaining, 4 - turn);
+        }
+
+        state.sim_next_turn();
+        assert_eq!(factory(&state, 1), (1, 7));
+        assert!(state.troops.is_empty());
+    }
+
+    #[test]
+    fn owned_factories_produce_every_turn() {
+        let mut state = new_state(&[(0, 1, 0, 3), (1, -1, 5, 1), (2, 0, 2, 3)], 2);
+        for _ in 0..3 {
+            state.sim_next_turn();
+        }
+
+        assert_eq!(factory(&state, 0), (1, 9));
+        assert_eq!(factory(&state, 1), (-1, 8));
+        assert_eq!(factory(&state, 2), (0, 2));
+    }
+
+    #[test]
+    fn orders_from_another_owner_are_ignored() {
+        let mut state = new_state(&[(0, -1, 10, 0), (1, 1, 0, 0)], 2);
+        state.troop_commands.push_back(troop(1, 0, 1, 5, 999));
+        state.sim_next_turn();
+
+        assert_eq!(factory(&state, 0), (-1, 10));
+        assert!(state.troops.is_empty());
+    }
+
+    // Random factories, troops in flight and orders from the owned factories
+    fn random_state(rng: &mut Rng) -> GameState {
+        let factory_count = rng.gen_range(2, 9);
+        let mut state = GameState::new();
+        for i in 0..factory_count {
+            for j in (i + 1)..factory_count {
+                state.factory_distance.insert((i, j), rng.gen_range(1, 8));
+            }
+        }
+        state.init_factories_distance(factory_count);
+
+        for factory in state.factories.values_mut() {
+            factory.owner = rng.gen_range(-1, 2);
+            factory.cyborg_count = rng.gen_range(0, 40);
+            factory.production = rng.gen_range(0, 4);
+        }
+
+        for _ in 0..rng.gen_range(0, 10) {
+            let (start, end) = (rng.gen_range(0, factory_count), rng.gen_range(0, factory_count));
+            if start == end { continue }
+            let owner = if rng.gen_bool() { 1 } else { -1 };
+            let distance = state.distance(start, end);
+            state.troops.push_back(troop(owner, start, end, rng.gen_range(1, 30), rng.gen_range(1, distance + 1)));
+        }
+
+        for _ in 0..rng.gen_range(0, 6) {
+            let (start, end) = (rng.gen_range(0, factory_count), rng.gen_range(0, factory_count));
+            let owner = state.factories.get(&start).unwrap().owner;
+            if start == end || owner == 0 { continue }
+            state.troop_commands.push_back(troop(owner, start, end, rng.gen_range(1, 50), 999));
+        }
+
+        return state;
+    }
+
+    fn total_cyborgs(state: &GameState) -> i32 {
+        return state.total_cyborgs(1) + state.total_cyborgs(-1) + state.total_cyborgs(0);
+    }
+
+    #[test]
+    fn cyborgs_are_only_created_by_production_and_destroyed_by_pairs() {
+        let mut rng = Rng::new(36);
+        for case in 0..500 {
+            let mut state = random_state(&mut rng);
+            for _ in 0..10 {
+                let production: i32 = state.factories.values().filter(|fac| !fac.is_neutral()).map(|fac| fac.production).sum();
+                let fights = state.troops.iter().any(|troop| troop.turn_remaining == 1);
+                let before = total_cyborgs(&state);
+
+                state.sim_next_turn();
+                let losses = before + production - total_cyborgs(&state);
+
+                // Each fight kills as many cyborgs on both sides
+                assert!(losses >= 0 && losses % 2 == 0, "case {}: {} cyborgs lost", case, losses);
+                if !fights {
+                    assert_eq!(losses, 0, "case {}", case);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn owners_stay_valid_and_counts_never_negative() {
+        let mut rng = Rng::new(3600);
+        for case in 0..500 {
+            let mut state = random_state(&mut rng);
+            for _ in 0..10 {
+                state.sim_next_turn();
+
+                for factory in state.factories.values() {
+                    assert!(factory.owner >= -1 && factory.owner <= 1, "case {}: owner {}", case, factory.owner);
+                    assert!(factory.cyborg_count >= 0, "case {}: garrison {}", case, factory.cyborg_count);
+                }
+                for troop in state.troops.iter() {
+                    assert!(troop.owner == 1 || troop.owner == -1, "case {}: troop owner {}", case, troop.owner);
+                    assert!(troop.cyborg_count > 0 && troop.turn_remaining > 0, "case {}", case);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn opposing_troops_fight_each_other_before_the_garrison() {
+        let mut state = new_state(&[(0, 0, 5, 0), (1, 1, 0, 0), (2, -1, 0, 0)], 2);
+        state.troops.push_back(troop(1, 1, 0, 10, 1));
+        state.troops.push_back(troop(-1, 2, 0, 8, 1));
+        state.sim_next_turn();
+
+        // 2 player cyborgs left against the 5 neutral ones
+        assert_eq!(factory(&state, 0), (0, 3));
+        assert!(state.troops.is_empty());
+    }
+
+    #[test]
+    fn survivors_capture_the_factory() {
+        let mut state = new_state(&[(0, 0, 5, 0), (1, 1, 0, 0), (2, -1, 0, 0)], 2);
+        state.troops.push_back(troop(1, 1, 0, 4, 1));
+        state.troops.push_back(troop(-1, 2, 0, 12, 1));
+        state.sim_next_turn();
+
+        assert_eq!(factory(&state, 0), (-1, 3));
+    }
+
+    #[test]
+    fn survivors_reinforce_their_own_factory() {
+        let mut state = new_state(&[(0, 1, 2, 1), (1, 1, 0, 0), (2, -1, 0, 0)], 2);
+        state.troops.push_back(troop(1, 1, 0, 9, 1));
+        state.troops.push_back(troop(-1, 2, 0, 6, 1));
+        state.sim_next_turn();
+
+        // Garrison 2 + production 1 + 3 surviving cyborgs
+        assert_eq!(factory(&state, 0), (1, 6));
+    }
+
+    #[test]
+    fn tie_with_the_garrison_keeps_the_owner() {
+        let mut state = new_state(&[(0, 1, 4, 1), (1, 1, 0, 0), (2, -1, 0, 0)], 2);
+        state.troops.push_back(troop(-1, 2, 0, 5, 1));
+        state.sim_next_turn();
+
+        assert_eq!(factory(&state, 0), (1, 0));
+    }
+
+    #[test]
+    fn equal_troops_cancel_out() {
+        let mut state = new_state(&[(0, 0, 1, 0), (1, 1, 0, 0), (2, -1, 0, 0)], 2);
+        state.troops.push_back(troop(1, 1, 0, 7, 1));
+        state.troops.push_back(troop(-1, 2, 0, 7, 1));
+        state.sim_next_turn();
+
+        assert_eq!(factory(&state, 0), (0, 1));
+    }
+
+    #[test]
+    fn arriving_cyborgs_cannot_leave_the_same_turn() {
+        let mut state = new_state(&[(0, 1, 0, 0), (1, 0, 0, 0)], 3);
+        state.troops.push_back(troop(1, 1, 0, 10, 1));
+        state.troop_commands.push_back(troop(1, 0, 1, 10, 999));
+        state.sim_next_turn();
+
+        assert_eq!(factory(&state, 0), (1, 10));
+        assert!(state.troops.is_empty());
+    }
+
+    #[test]
+    fn inc_costs_10_cyborgs_up_to_production_3() {
+        let mut state = new_state(&[(0, 1, 25, 1), (1, 1, 9, 1), (2, -1, 30, 3)], 2);
+        state.commands.push("INC 0".to_string());
+        state.commands.push("INC 1".to_string());
+        state.commands.push("INC 2".to_string());
+        state.sim_next_turn();
+
+        assert_eq!(state.factories.get(&0).unwrap().production, 2);
+        assert_eq!(factory(&state, 0), (1, 17));
+        // Not enough cyborgs
+        assert_eq!(state.factories.get(&1).unwrap().production, 1);
+        assert_eq!(factory(&state, 1), (1, 10));
+        // Already at max production
+        assert_eq!(state.factories.get(&2).unwrap().production, 3);
+        assert_eq!(factory(&state, 2), (-1, 33));
+        assert!(state.commands.is_empty());
+    }
+
+    #[test]
+    fn bomb_destroys_half_the_garrison_and_stops_production() {
+        let mut state = new_state(&[(0, 1, 0, 1), (1, -1, 40, 2), (2, -1, 6, 2)], 1);
+        state.commands.push("BOMB 0 1".to_string());
+        state.sim_next_turn();
+        assert_eq!(state.bombs.len(), 1);
+
+        state.sim_next_turn();
+        // 40 + 2 + 2 produced, then half of the 44 destroyed
+        assert_eq!(factory(&state, 1), (-1, 22));
+        assert!(state.bombs.is_empty());
+
+        for _ in 0..BOMB_DISRUPTION {
+            state.sim_next_turn();
+            assert_eq!(factory(&state, 1), (-1, 22));
+        }
+        state.sim_next_turn();
+        assert_eq!(factory(&state, 1), (-1, 24));
+    }
+
+    #[test]
+    fn bomb_destroys_at_least_10_cyborgs() {
+        let mut state = new_state(&[(0, 1, 0, 0), (1, -1, 6, 0)], 1);
+        state.bombs.push(Bomb{id: 999, owner: 1, factory_start: 0, factory_end: 1, turn_remaining: 1});
+        state.sim_next_turn();
+
+        assert_eq!(factory(&state, 1), (-1, 0));
+        assert_eq!(state.factories.get(&1).unwrap().disrupted, BOMB_DISRUPTION);
+    }
+
+    #[test]
+    fn game_ends_on_elimination() {
+        let mut state = new_state(&[(0, 1, 10, 1), (1, -1, 0, 0), (2, 0, 0, 0)], 2);
+        assert!(state.is_terminal());
+        assert_eq!(state.winner(), 1);
+
+        // Troops in flight keep the enemy alive
+        state.troops.push_back(troop(-1, 1, 2, 3, 1));
+        assert!(!state.is_terminal());
+    }
+
+    #[test]
+    fn game_ends_at_the_turn_limit_with_the_cyborg_count() {
+        let mut state = new_state(&[(0, 1, 10, 0), (1, -1, 8, 0)], 2);
+        state.troops.push_back(troop(-1, 1, 0, 3, 5));
+        state.nb_turn = MAX_TURNS - 1;
+        assert!(!state.is_terminal());
+
+        state.sim_next_turn();
+        assert!(state.is_terminal());
+        assert_eq!(state.total_cyborgs(-1), 11);
+        assert_eq!(state.winner(), -1);
+    }
+
+    #[test]
+    fn same_cyborg_count_is_a_draw() {
+        let mut state = new_state(&[(0, 1, 10, 1), (1, -1, 10, 1)], 2);
+        state.nb_turn = MAX_TURNS;
+        assert!(state.is_terminal());
+        assert_eq!(state.winner(), 0);
+    }
+
+    #[test]
+    fn orders_travel_the_link_distance() {
+        let mut state = new_state(&[(0, 1, 10, 0), (1, 0, 0, 0)], 3);
+        state.troop_commands.push_back(troop(1, 0, 1, 6, 999));
+        state.sim_next_turn();
+
+        assert_eq!(factory(&state, 0), (1, 4));
+        assert_eq!(state.troops.front().unwrap().turn_remaining, 3);
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::BTreeSet;
+
+use crate::model::{GameState, HasOwner, Snapshot, BOMB_DISRUPTION};
+use crate::trace::{trace_enabled, Category, Level};
+
+impl GameState {
+    // Simulate the last turn with our orders only and compare with the current turn what the enemy orders
+    // of that turn cannot change: our troops, the enemy troops launched before, and the factories that were
+    // not the enemy's and were not hit by an enemy bomb. Any difference is a rule of the simulator gone wrong.
+    pub fn sim_check(&self, last: &Snapshot) -> Vec<String> {
+        let mut predicted = self.restore(last);
+        predicted.sim_next_turn();
+
+        let known_troops: BTreeSet<i32> = last.troops.iter().map(|troop| troop.id).collect();
+        let mut seen = self.clone();
+        seen.troops = self.troops.iter()
+            .filter(|troop| troop.is_player() || known_troops.contains(&troop.id))
+            .cloned()
+            .collect();
+        seen.factories.retain(|id, factory| {
+            let simulated = predicted.factories.get(id).unwrap();
+            let bombed = factory.disrupted == BOMB_DISRUPTION && simulated.disrupted != BOMB_DISRUPTION;
+            !last.factories.get(id).unwrap().is_enemy() && !bombed
+        });
+        return predicted.discrepancies(&seen);
+    }
+
+    // Online check of the simulator, on with GITC_TRACE="sim=info"
+    pub fn check_simulation(&self, last: &Snapshot) {
+        if !trace_enabled(Category::Sim, Level::Info) { return }
+        let mismatches = self.sim_check(last);
+        if !mismatches.is_empty() {
+            trace!(Category::Sim, Level::Info, "mismatch", "turn" => last.nb_turn, "count" => mismatches.len(), "mismatches" => mismatches);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::referee_turn;
+
+    #[test]
+    fn unseen_enemy_orders_are_not_mismatches() {
+        let (last, next) = referee_turn();
+        assert!(next.sim_check(&last).is_empty());
+    }
+
+    #[test]
+    fn simulator_errors_are_reported() {
+        let (last, mut next) = referee_turn();
+        next.factories.get_mut(&3).unwrap().cyborg_count = 7;
+        next.troops.front_mut().unwrap().turn_remaining = 2;
+
+        assert_eq!(next.sim_check(&last), vec!["factory 3 (1, 7, 2, 0) simulated (1, 8, 2, 0)".to_string(),
+                                               "troop (1, 0, 2, 5, 2) not simulated".to_string(),
+                                               "troop (1, 0, 2, 5, 3) simulated only".to_string()]);
+    }
+}
@@ -0,0 +1,359 @@
+use std::rc::Rc;
+
+use crate::model::{Factory, GameState, HasOwner, Troop};
+use crate::territory::Zone;
+use crate::trace::{trace_enabled, trace_turn, Category, Level};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Brain {
+    Rules,
+    RandomSearch
+}
+
+impl GameState {
+    // Orders are left in troop_commands and commands
+    pub fn play_turn(&mut self, brain: Brain) {
+        trace_turn(self.nb_turn);
+        self.update_territory();
+        self.update_tracked_troops();
+        self.update_profile();
+        if trace_enabled(Category::Turn, Level::Debug) {
+            self.print_factories();
+            for troop in self.troops.iter() {
+                trace!(Category::Turn, Level::Debug, "troop", "id" => troop.id, "owner" => troop.owner, "from" => troop.factory_start,
+                       "to" => troop.factory_end, "count" => troop.cyborg_count, "turns" => troop.turn_remaining,
+                       "launched" => self.tracked_troops.get(&troop.id).unwrap().launch_turn);
+            }
+        }
+
+        match brain {
+            Brain::Rules => self.defend_strategy(),
+            Brain::RandomSearch => *self = self.random_search()
+        }
+        self.compute_bomb();
+
+        let mut orders = self.commands.clone();
+        for troop in self.troop_commands.iter() {
+            orders.push(format!("MOVE {} {} {}", troop.factory_start, troop.factory_end, troop.cyborg_count));
+        }
+        trace!(Category::Turn, Level::Info, "orders", "orders" => orders.clone());
+        // Kept to simulate this turn once the next one is known
+        if let Some(last) = self.last_seen.as_mut() {
+            Rc::make_mut(last).orders = orders;
+        }
+    }
+
+    pub fn random_strategy(&mut self) {
+        let factory_count: i32 = self.factories.len() as i32;
+        for factory in self.factories.values_mut() {
+            if !factory.is_player() { continue }
+
+            let cyborg_count = self.rng.gen_range(0, factory.cyborg_remaining + 1);
+
+            if cyborg_count == 0 { continue }
+
+            factory.cyborg_remaining -= cyborg_count;
+
+            let mut target = self.rng.gen_range(0, factory_count);
+
+            while target == factory.id {
+                target = self.rng.gen_range(0, factory_count);
+            }
+
+            self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory.id, factory_end: target, cyborg_count: cyborg_count, turn_remaining: 10});
+        }
+
+    }
+
+    pub fn max_strategy(&mut self) {
+
+        let max_factory_option = self.factories.iter()
+            .filter(|&(_, fac)| fac.is_player())
+            .max_by_key(|&(_, fac)| fac.cyborg_remaining);
+
+        if max_factory_option.is_none() { return }
+
+        let max_factory = max_factory_option.unwrap().1;
+
+        // Closest factory
+        for &(distance, id2) in max_factory.distances.iter() {
+            let factory2 = self.factories.get(&id2).unwrap();
+            if !factory2.is_player() && factory2.production > 0 {
+                //self.commands.push(format!("MOVE {} {} {}", max_factory.id, id2, max_factory.cyborg_count));
+                self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: max_factory.id, factory_end: id2, cyborg_count: max_factory.cyborg_remaining, turn_remaining: distance});
+                trace!(Category::Attack, Level::Info, "move", "from" => max_factory.id, "to" => id2, "count" => max_factory.cyborg_remaining,
+                       "reason" => "biggest garrison to the closest producing factory");
+                break;
+            }
+        }
+
+    }
+
+    pub fn targeted_attack_strategy(&mut self) {
+
+        /*
+        { // Dont attack if prod not full
+            let factory_prod_not_max_option = self.factories.iter()
+                .filter(|&(ind, fac)| fac.is_player() && fac.production < 3);
+            if factory_prod_not_max_option.count() > 0 {
+                print_err!("Waiting prod");
+                return;
+            }
+        }
+    */
+        let mut min_dist = 999;
+        let mut target: i32 = -1;
+        let factories_immu = self.factories.clone();
+        for (id, enemy_fac) in self.factories.iter() {
+            if !enemy_fac.is_enemy() { continue }
+            if enemy_fac.production == 0 { continue }
+            if self.budget.should_stop() {
+                trace!(Category::Timing, Level::Info, "deadline", "strategy" => "attack", "remaining_ms" => self.budget.remaining().as_millis() as i32);
+                break;
+            }
+
+            let mut turn = -1;
+            let mut state = self.clone();
+            while !state.factories.get(id).unwrap().is_player() && turn < self.params.lookahead && !state.is_terminal() {
+                //print_err!("turn : {} id{} owner{}", turn, state.factories.get(&id).unwrap().id, state.factories.get(&id).unwrap().owner);
+                state.sim_next_turn();
+                turn += 1;
+            }
+            if state.factories.get(id).unwrap().is_player() {
+                trace!(Category::Attack, Level::Debug, "skip_target", "factory" => id, "captured_in" => turn, "reason" => "already captured by our troops");
+                continue;
+            }
+
+
+            let mut sum_dist = 0;
+            for &(distance, id2) in enemy_fac.distances.iter() {
+                let player_fac = factories_immu.get(&id2).unwrap();
+
+                if player_fac.is_player() {
+                    sum_dist += distance;
+                }
+
+            }
+
+            trace!(Category::Attack, Level::Debug, "candidate", "factory" => enemy_fac.id, "sum_distance" => sum_dist);
+            if min_dist > sum_dist {
+                min_dist = sum_dist;
+                target = enemy_fac.id;
+            }
+        }
+
+        if target == -1 { return }
+
+        trace!(Category::Attack, Level::Info, "target", "factory" => target, "sum_distance" => min_dist);
+
+        for fac in self.factories.values_mut() {
+            if !fac.is_player() { continue }
+            // The backline feeds the frontline instead, see supply_frontline
+            if self.territory.zone(fac.id) == Zone::Backline { continue }
+
+            if fac.cyborg_remaining > 0 {
+                self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: fac.id, factory_end: target, cyborg_count: fac.cyborg_remaining, turn_remaining: 999});
+                trace!(Category::Attack, Level::Info, "move", "from" => fac.id, "to" => target, "count" => fac.cyborg_remaining, "reason" => "targeted attack");
+                fac.cyborg_remaining = 0;
+            }
+
+        }
+
+    }
+
+    pub fn neutral_first_strategy(&mut self) {
+        let factories_immu = self.factories.clone();
+        for factory in self.factories.values_mut() {
+
+            if factory.is_player() {
+                for &(distance, id2) in factory.distances.iter() {
+                    let fac_target = factories_immu.get(&id2).unwrap();
+                    if fac_target.production > 0 {
+
+                        if fac_target.is_neutral() && fac_target.cyborg_count < factory.cyborg_remaining {
+                            let is_enemy_closest = self.territory.get(fac_target.id).enemy_distance < distance;
+                            if !is_enemy_closest {
+                                if self.bombing {
+                                    self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory.id, factory_end: fac_target.id, cyborg_count: factory.cyborg_count, turn_remaining: distance});
+                                    trace!(Category::Neutral, Level::Info, "move", "from" => factory.id, "to" => fac_target.id, "count" => factory.cyborg_count,
+                                           "reason" => "bomb incoming, evacuate to the neutral");
+                                    factory.cyborg_remaining -= factory.cyborg_count;
+                                } else {
+                                    self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory.id, factory_end: fac_target.id, cyborg_count: fac_target.cyborg_count +1, turn_remaining: distance});
+                                    trace!(Category::Neutral, Level::Info, "move", "from" => factory.id, "to" => fac_target.id, "count" => fac_target.cyborg_count + 1,
+                                           "reason" => "neutral closer to us than to the enemy");
+                                    factory.cyborg_remaining -= fac_target.cyborg_count +1;
+                                }
+
+                            }
+                        }
+                    }
+                }
+            }
+
+        }
+
+        if self.troop_commands.is_empty() {
+            self.targeted_attack_strategy();
+        }
+
+    }
+
+    pub fn compute_inc(&mut self) {
+        if self.nb_turn == 0 { return }
+
+        let factories_immu = self.factories.clone();
+        let inc_threshold = self.params.inc_threshold;
+        let mut troops_sent = Vec::new();
+        for (id, factory) in self.factories.iter_mut() {
+            if !factory.is_player() { continue }
+            if factory.production == 3 { continue }
+
+            if factory.cyborg_remaining > inc_threshold {
+                factory.cyborg_remaining -= 10;
+                self.commands.push(format!("INC {}", id));
+                trace!(Category::Inc, Level::Info, "inc", "factory" => id, "remaining" => factory.cyborg_remaining);
+            } else {
+
+                // Find a possible troop donator to Inc
+                for &(distance, id2) in factory.distances.iter() {
+
+                    if factory.production * distance > inc_threshold - factory.cyborg_remaining { break }
+
+                    let factory_renfort = factories_immu.get(&id2).unwrap();
+
+                    if !factory_renfort.is_player() { continue }
+                    if factory_renfort.production < 3 { continue }
+                    if inc_threshold - factory.cyborg_remaining < factory_renfort.cyborg_remaining {
+                        trace!(Category::Inc, Level::Info, "help", "factory" => factory.id, "from" => factory_renfort.id,
+                               "count" => inc_threshold - factory.cyborg_remaining, "reason" => "reach the INC threshold");
+                        self.troop_commands.push_back(Troop{id: 999, owner: 1, factory_start: factory_renfort.id, factory_end: factory.id, cyborg_count: inc_threshold - factory.cyborg_remaining, turn_remaining: distance});
+                        troops_sent.push(Troop{id: 999, owner: 1, factory_start: factory_renfort.id, factory_end: factory.id, cyborg_count: inc_threshold - factory.cyborg_remaining, turn_remaining: distance});
+                        // factory_renfort.cyborg_remaining -= 15 - factory.cyborg_remaining
+                        break;
+                    }
+
+                }
+
+            }
+        }
+        for troop in troops_sent.iter() {
+            let factory_renfort = self.factories.get_mut(&troop.factory_start).unwrap();
+            factory_renfort.cyborg_remaining -= troop.cyborg_count;
+        }
+
+    }
+
+    pub fn defend_strategy(&mut self) {
+
+        // Keep the garrison the worst case enemy attack requires, launched or not
+        let threats = self.compute_threats(self.params.threat_horizon);
+        for (id, threat) in threats.iter() {
+            let factory = self.factories.get_mut(id).unwrap();
+            let spare = (factory.cyborg_count - threat.required_garrison).max(0);
+            if spare < factory.cyborg_remaining {
+                trace!(Category::Defend, Level::Info, "hold", "factory" => *id, "garrison" => factory.cyborg_count, "required" => threat.required_garrison,
+                       "deadline" => threat.deadline, "reason" => "worst case enemy arrivals");
+                factory.cyborg_remaining = spare;
+            }
+        }
+
+        self.reinforce();
+        self.counter_attack();
+
+        self.compute_inc();
+
+        if self.troop_commands.is_empty() {
+            self.neutral_first_strategy();
+        }
+        self.allocate_moves();
+
+        self.supply_frontline();
+
+    }
+
+    pub fn swarm_strategy(&mut self) {
+        for factory in self.factories.values() {
+            if factory.is_player() && factory.cyborg_count > self.factories.len() as i32 {
+                let id1 = factory.id;
+
+                for factory2 in self.factories.values() {
+                    if !factory2.is_player() {
+                        let id2 = factory2.id;
+                        self.commands.push(format!("MOVE {} {} {}", id1, id2, 1));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn compute_bomb(&mut self) {
+        if self.bomb_count == 0 { return }
+
+        // Get the max prod
+        let mut factory_prod = 1;
+        for factory in self.factories.values() {
+            if factory.is_enemy() && factory.production > factory_prod {
+                factory_prod = factory.production;
+                break;
+            }
+        }
+
+        // Get the target
+        let mut aimed_factory: &Factory = &Factory{id: -99, owner: -99, cyborg_count: -99, production: -99, distances: Vec::new(), disrupted: 0, cyborg_remaining: 0};
+        for factory in self.factories.values() {
+            if factory.is_enemy() && factory.cyborg_count > aimed_factory.cyborg_count && factory.production == factory_prod && self.bomb_last != factory.id {
+                aimed_factory = factory;
+            }
+        }
+
+        if aimed_factory.id < 0 { return }
+
+        // Get the source (the closest)
+        for &(_, id2) in aimed_factory.distances.iter() {
+            let factory2 = self.factories.get(&id2).unwrap();
+            if factory2.is_player() {
+                self.bomb_count -= 1;
+                self.bomb_last = aimed_factory.id;
+                self.commands.push(format!("BOMB {} {}", id2, aimed_factory.id));
+                trace!(Category::Bomb, Level::Info, "bomb", "from" => id2, "to" => aimed_factory.id, "production" => aimed_factory.production,
+                       "garrison" => aimed_factory.cyborg_count, "bombs_left" => self.bomb_count);
+                return;
+            }
+        }
+
+    }
+
+    pub fn print_factories(&mut self) {
+        for factory in self.factories.values() {
+            trace!(Category::Turn, Level::Debug, "factory", "id" => factory.id, "owner" => factory.owner,
+                   "count" => factory.cyborg_count, "production" => factory.production);
+        }
+    }
+
+    pub fn random_search(&mut self) -> GameState {
+        let mut max_game = self.clone();
+        let mut max_score = self.evaluate();
+        let mut iterations = 0;
+        for _ in 0..1000 {
+            iterations += 1;
+            let mut game_cloned = self.clone();
+            game_cloned.rng = self.rng.fork();
+            game_cloned.random_strategy();
+            game_cloned.random_strategy();
+
+            let mut game_cloned_cloned = game_cloned.clone();
+            game_cloned_cloned.sim_next_turn();
+            let score = game_cloned_cloned.evaluate();
+            if score > max_score {
+                max_score = score;
+                max_game = game_cloned;
+            }
+
+            if self.budget.should_stop() { break }
+        }
+
+        trace!(Category::Search, Level::Info, "random_search", "iterations" => iterations, "score" => max_score);
+        return max_game;
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner};
+use crate::trace::{Category, Level};
+
+// Distance to a side that has no factory
+pub const NO_FACTORY: i32 = 999;
+// A neutral is contested when both sides are about as close
+const CONTESTED_MARGIN: i32 = 1;
+// Turns of production counted in the influence of a factory
+const INFLUENCE_PRODUCTION_TURNS: i32 = 5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Zone {
+    Frontline,
+    Backline,
+    Contested
+}
+
+impl Zone {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Zone::Frontline => "frontline",
+            Zone::Backline => "backline",
+            Zone::Contested => "contested"
+        }
+    }
+}
+
+// Friend and enemy are from our point of view, the zone of an owned factory from its owner's:
+// frontline when no ally stands between it and the nearest hostile factory, backline otherwise.
+// A neutral is contested when both sides are as close, backline on our side, frontline on the enemy side.
+#[derive(Clone)]
+pub struct FactoryTerritory {
+    pub friend_distance: i32, // Nearest other factory of ours, NO_FACTORY if none
+    pub enemy_distance: i32, // Nearest other enemy factory, NO_FACTORY if none
+    pub zone: Zone,
+    pub influence: f32 // Garrisons and production around, > 0 when we dominate
+}
+
+#[derive(Clone, Default)]
+pub struct Territory {
+    pub factories: BTreeMap<i32, FactoryTerritory>
+}
+
+impl Territory {
+    pub fn get(&self, id: i32) -> &FactoryTerritory {
+        return self.factories.get(&id).unwrap();
+    }
+
+    pub fn zone(&self, id: i32) -> Zone {
+        return self.get(id).zone;
+    }
+}
+
+impl GameState {
+    pub fn compute_territory(&self) -> Territory {
+        let mut territory = Territory::default();
+        for factory in self.factories.values() {
+            let nearest = |owner: i32| factory.distances.iter()
+                .find(|&&(_, id2)| self.factories.get(&id2).unwrap().owner == owner)
+                .map_or(NO_FACTORY, |&(distance, _)| distance);
+            let friend_distance = nearest(1);
+            let enemy_distance = nearest(-1);
+
+            let zone = if factory.is_neutral() {
+                let gap = enemy_distance - friend_distance;
+                if gap.abs() <= CONTESTED_MARGIN {
+                    Zone::Contested
+                } else if gap > 0 {
+                    Zone::Backline
+                } else {
+                    Zone::Frontline
+                }
+            } else {
+                self.owned_zone(factory.id)
+            };
+
+            // The factory itself counts at distance 0
+            let mut influence = factory.owner as f32 * (factory.cyborg_count + factory.production * INFLUENCE_PRODUCTION_TURNS) as f32;
+            for &(distance, id2) in factory.distances.iter() {
+                let other = self.factories.get(&id2).unwrap();
+                influence += other.owner as f32 * (other.cyborg_count + other.production * INFLUENCE_PRODUCTION_TURNS) as f32 / (distance + 1) as f32;
+            }
+
+            territory.factories.insert(factory.id, FactoryTerritory{friend_distance: friend_distance, enemy_distance: enemy_distance, zone: zone, influence: influence});
+        }
+        return territory;
+    }
+
+    fn owned_zone(&self, id: i32) -> Zone {
+        let factory = self.factories.get(&id).unwrap();
+        let side = factory.owner;
+        let hostile = factory.distances.iter().find(|&&(_, id2)| self.factories.get(&id2).unwrap().owner == -side);
+        let (hostile_distance, hostile_id) = match hostile {
+            Some(&hostile) => hostile,
+            None => return Zone::Backline
+        };
+
+        // An ally closer to the hostile factory and on the way covers this one
+        for &(distance, id2) in factory.distances.iter() {
+            if distance >= hostile_distance { break }
+            if self.factories.get(&id2).unwrap().owner != side { continue }
+            if self.distance(id2, hostile_id) < hostile_distance {
+                return Zone::Backline;
+            }
+        }
+        return Zone::Frontline;
+    }
+
+    pub fn update_territory(&mut self) {
+        self.territory = self.compute_territory();
+        for (id, area) in self.territory.factories.iter() {
+            trace!(Category::Map, Level::Debug, "territory", "factory" => *id, "zone" => area.zone.name(), "friend_distance" => area.friend_distance,
+                   "enemy_distance" => area.enemy_distance, "influence" => area.influence);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::new_map;
+
+    // 0 - 1 - 2 - 3 - 4 on a line, one turn apart
+    fn line_map(owners: [i32; 5]) -> GameState {
+        let factories: Vec<_> = owners.iter().enumerate().map(|(id, &owner)| (id as i32, owner, 10, 1)).collect();
+        let mut links = Vec::new();
+        for i in 0..5 {
+            for j in (i + 1)..5 {
+                links.push((i, j, j - i));
+            }
+        }
+        return new_map(&factories, &links);
+    }
+
+    #[test]
+    fn factories_between_the_sides_are_frontline() {
+        let territory = line_map([1, 1, 0, -1, -1]).compute_territory();
+
+        let zones: Vec<Zone> = (0..5).map(|id| territory.zone(id)).collect();
+        assert_eq!(zones, vec![Zone::Backline, Zone::Frontline, Zone::Contested, Zone::Frontline, Zone::Backline]);
+        assert_eq!((territory.get(0).friend_distance, territory.get(0).enemy_distance), (1, 3));
+    }
+
+    #[test]
+    fn neutrals_are_labelled_by_the_closest_side() {
+        let territory = line_map([1, 0, 0, 0, -1]).compute_territory();
+
+        assert_eq!(territory.zone(1), Zone::Backline);
+        assert_eq!(territory.zone(2), Zone::Contested);
+        assert_eq!(territory.zone(3), Zone::Frontline);
+    }
+
+    #[test]
+    fn influence_follows_garrisons_and_production() {
+        let mut state = line_map([1, 0, 0, 0, -1]);
+        state.factories.get_mut(&0).unwrap().cyborg_count = 30;
+        let territory = state.compute_territory();
+
+        assert!(territory.get(1).influence > 0.0);
+        assert!(territory.get(2).influence > 0.0);
+        assert!(territory.get(4).influence < 0.0);
+        assert!(territory.get(0).influence > territory.get(1).influence);
+    }
+
+    #[test]
+    fn a_side_without_factories_is_out_of_reach() {
+        let territory = line_map([1, 1, 0, 0, 0]).compute_territory();
+
+        assert_eq!(territory.get(0).enemy_distance, NO_FACTORY);
+        assert_eq!(territory.zone(0), Zone::Backline);
+        assert_eq!(territory.zone(4), Zone::Backline);
+    }
+}
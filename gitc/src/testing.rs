@@ -0,0 +1,70 @@
+// Helpers shared by the tests of every module
+use crate::model::{Bomb, GameState, Snapshot, Troop};
+
+// (id, owner, cyborg_count, production) and the distance of every link
+pub fn new_state(factories: &[(i32, i32, i32, i32)], distance: i32) -> GameState {
+    let mut links = Vec::new();
+    for i in 0..factories.len() as i32 {
+        for j in (i + 1)..factories.len() as i32 {
+            links.push((i, j, distance));
+        }
+    }
+    return new_map(factories, &links);
+}
+
+// (id, owner, cyborg_count, production) and (id1, id2, distance) for every link, the whole garrison is spare like after init_entities
+pub fn new_map(factories: &[(i32, i32, i32, i32)], links: &[(i32, i32, i32)]) -> GameState {
+    let mut state = GameState::new();
+    for &(id1, id2, distance) in links.iter() {
+        state.factory_distance.insert((id1, id2), distance);
+    }
+    state.init_factories_distance(factories.len() as i32);
+
+    for &(id, owner, cyborg_count, production) in factories.iter() {
+        let factory = state.factories.get_mut(&id).unwrap();
+        factory.owner = owner;
+        factory.cyborg_count = cyborg_count;
+        factory.production = production;
+        factory.cyborg_remaining = cyborg_count;
+    }
+    return state;
+}
+
+pub fn troop(owner: i32, factory_start: i32, factory_end: i32, cyborg_count: i32, turn_remaining: i32) -> Troop {
+    return Troop{id: 999, owner: owner, factory_start: factory_start, factory_end: factory_end, cyborg_count: cyborg_count, turn_remaining: turn_remaining};
+}
+
+pub fn factory(state: &GameState, id: i32) -> (i32, i32) {
+    let factory = state.factories.get(&id).unwrap();
+    return (factory.owner, factory.cyborg_count);
+}
+
+// (from, to, count) of the MOVE orders given this turn
+pub fn orders(state: &GameState) -> Vec<(i32, i32, i32)> {
+    return state.troop_commands.iter().map(|troop| (troop.factory_start, troop.factory_end, troop.cyborg_count)).collect();
+}
+
+// A turn and the referee input of the next one. We are 0 and send 5 cyborgs to the neutral 2, the enemy 1 has a bomb
+// and 4 cyborgs landing on our 3. The enemy then launched 8 cyborgs to 2 and INC 1, its bomb exploded on 0
+pub fn referee_turn() -> (Snapshot, GameState) {
+    let mut state = new_map(&[(0, 1, 20, 1), (1, -1, 30, 1), (2, 0, 5, 2), (3, 1, 10, 2)],
+                            &[(0, 1, 6), (0, 2, 3), (1, 2, 3), (0, 3, 4), (1, 3, 2), (2, 3, 5)]);
+    state.bombs.push(Bomb{id: 4, owner: -1, factory_start: 1, factory_end: -1, turn_remaining: -1});
+    state.troops.push_back(Troop{id: 5, ..troop(-1, 1, 3, 4, 1)});
+    let mut last = state.snapshot();
+    last.orders = vec!["MOVE 0 2 5".to_string()];
+
+    let mut next = state;
+    next.nb_turn += 1;
+    next.troops.clear();
+    next.troops.push_back(Troop{id: 6, ..troop(1, 0, 2, 5, 3)});
+    next.troops.push_back(Troop{id: 7, ..troop(-1, 1, 2, 8, 3)});
+    next.bombs.clear();
+    for &(id, count, production, disrupted) in [(0, 6, 1, 5), (1, 14, 2, 0), (3, 8, 2, 0)].iter() {
+        let factory = next.factories.get_mut(&id).unwrap();
+        factory.cyborg_count = count;
+        factory.production = production;
+        factory.disrupted = disrupted;
+    }
+    return (last, next);
+}
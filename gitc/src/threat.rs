@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner};
+use crate::trace::{Category, Level};
+
+// Worst case for one of our factories: every enemy factory within `horizon` launches its whole
+// garrison as late as possible to arrive by turn t, on top of the enemy troops already on their way.
+// Index t of the vectors is the end of turn t, 0 being now. An order given now travels for distance + 1 turns.
+#[derive(Clone)]
+pub struct Threat {
+    pub enemy: Vec<i32>, // Enemy cyborgs arrived by turn t
+    pub support: Vec<i32>, // Our production and troops arrived by turn t, without the garrison
+    pub required_garrison: i32, // Garrison needed now to hold every turn
+    pub deadline: i32 // First turn the current garrison falls short, -1 if it holds
+}
+
+impl Threat {
+    // Missing cyborgs at turn t with the current garrison
+    pub fn deficit(&self, turn: i32, garrison: i32) -> i32 {
+        return self.enemy[turn as usize] - self.support[turn as usize] - garrison;
+    }
+}
+
+impl GameState {
+    // A horizon of 0 only counts the launched attacks
+    pub fn compute_threat(&self, id: i32, horizon: i32) -> Threat {
+        let factory = self.factories.get(&id).unwrap();
+
+        let mut last_turn = horizon;
+        for troop in self.troops.iter() {
+            if troop.factory_end == id { last_turn = last_turn.max(troop.turn_remaining) }
+        }
+
+        let mut threat = Threat{enemy: vec![0; last_turn as usize + 1], support: vec![0; last_turn as usize + 1], required_garrison: 0, deadline: -1};
+        for turn in 1..=last_turn {
+            let t = turn as usize;
+            threat.support[t] = factory.production * (turn - factory.disrupted).max(0);
+
+            for troop in self.troops.iter() {
+                if troop.factory_end != id || troop.turn_remaining > turn { continue }
+                if troop.is_enemy() {
+                    threat.enemy[t] += troop.cyborg_count;
+                } else {
+                    threat.support[t] += troop.cyborg_count;
+                }
+            }
+
+            for &(distance, id2) in factory.distances.iter() {
+                if distance > horizon || distance + 1 > turn { break }
+                let enemy_factory = self.factories.get(&id2).unwrap();
+                if !enemy_factory.is_enemy() { continue }
+                threat.enemy[t] += enemy_factory.cyborg_count + enemy_factory.production * (turn - distance - 1 - enemy_factory.disrupted).max(0);
+            }
+
+            let deficit = threat.deficit(turn, factory.cyborg_count);
+            threat.required_garrison = threat.required_garrison.max(factory.cyborg_count + deficit);
+            if deficit > 0 && threat.deadline == -1 {
+                threat.deadline = turn;
+            }
+        }
+        return threat;
+    }
+
+    pub fn compute_threats(&self, horizon: i32) -> BTreeMap<i32, Threat> {
+        let mut threats = BTreeMap::new();
+        for factory in self.factories.values() {
+            if !factory.is_player() { continue }
+            let threat = self.compute_threat(factory.id, horizon);
+            trace!(Category::Defend, Level::Debug, "threat", "factory" => factory.id, "garrison" => factory.cyborg_count,
+                   "required" => threat.required_garrison, "deadline" => threat.deadline, "enemy" => threat.enemy.clone());
+            threats.insert(factory.id, threat);
+        }
+        return threats;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{new_map, troop};
+
+    // Our factory 0, an enemy factory 1 three turns away and a far enemy factory 2
+    fn state() -> GameState {
+        return new_map(&[(0, 1, 10, 2), (1, -1, 12, 1), (2, -1, 30, 3)], &[(0, 1, 3), (0, 2, 15), (1, 2, 12)]);
+    }
+
+    #[test]
+    fn enemy_garrisons_in_range_are_a_threat() {
+        let threat = state().compute_threat(0, 5);
+
+        // Factory 1 arrives at turn 4 with 12 cyborgs, then produces one more per turn it waits
+        assert_eq!(threat.enemy, vec![0, 0, 0, 0, 12, 13]);
+        assert_eq!(threat.support, vec![0, 2, 4, 6, 8, 10]);
+        assert_eq!(threat.required_garrison, 4);
+        assert_eq!(threat.deadline, -1);
+        assert_eq!(state().compute_threat(0, 2).enemy, vec![0; 3]);
+    }
+
+    #[test]
+    fn troops_in_flight_count_whatever_the_horizon() {
+        let mut state = state();
+        state.troops.push_back(troop(-1, 2, 0, 25, 8));
+        state.troops.push_back(troop(1, 1, 0, 4, 4));
+        let threat = state.compute_threat(0, 5);
+
+        assert_eq!(threat.enemy.len(), 9);
+        assert_eq!(threat.enemy[8], 12 + 4 + 25);
+        assert_eq!(threat.support[8], 16 + 4);
+        assert_eq!(threat.required_garrison, 21);
+        assert_eq!(threat.deadline, 8);
+        assert_eq!(threat.deficit(8, 10), 11);
+    }
+
+    #[test]
+    fn bombed_factories_stop_producing() {
+        let mut state = state();
+        state.factories.get_mut(&0).unwrap().disrupted = 5;
+        let threat = state.compute_threat(0, 5);
+
+        assert_eq!(threat.support, vec![0; 6]);
+        assert_eq!(threat.required_garrison, 13);
+        assert_eq!(threat.deadline, 4);
+    }
+}
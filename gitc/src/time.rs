@@ -0,0 +1,31 @@
+use std::time::{Duration, Instant};
+
+pub const FIRST_TURN_BUDGET_MS: u64 = 1000;
+pub const SAFETY_MARGIN_MS: u64 = 5;
+
+// Deadline of the current turn, started once the turn input has been read
+#[derive(Clone, Copy)]
+pub struct TimeBudget {
+    pub start: Instant,
+    pub budget: Duration
+}
+
+impl TimeBudget {
+    pub fn start(nb_turn: i32, turn_budget_ms: i32) -> TimeBudget {
+        let budget_ms = if nb_turn == 0 { FIRST_TURN_BUDGET_MS } else { turn_budget_ms as u64 };
+        return TimeBudget{start: Instant::now(), budget: Duration::from_millis(budget_ms)};
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        return self.start.elapsed().as_millis() as u64;
+    }
+
+    pub fn remaining(&self) -> Duration {
+        return self.budget.checked_sub(self.start.elapsed()).unwrap_or(Duration::from_millis(0));
+    }
+
+    // Keep SAFETY_MARGIN_MS to finish the turn and print the commands
+    pub fn should_stop(&self) -> bool {
+        return self.remaining() <= Duration::from_millis(SAFETY_MARGIN_MS);
+    }
+}
@@ -0,0 +1,173 @@
+use std::env;
+use std::fs;
+use std::cell::RefCell;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Off,
+    Info,
+    Debug
+}
+
+#[derive(Clone, Copy)]
+pub enum Category {
+    Map,
+    Turn,
+    Defend,
+    Attack,
+    Neutral,
+    Inc,
+    Bomb,
+    Search,
+    Timing,
+    Logistics,
+    Opponent,
+    Sim
+}
+
+pub const CATEGORIES: [Category; 12] = [Category::Map, Category::Turn, Category::Defend, Category::Attack, Category::Neutral,
+                                    Category::Inc, Category::Bomb, Category::Search, Category::Timing, Category::Logistics, Category::Opponent,
+                                    Category::Sim];
+
+impl Category {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Category::Map => "map",
+            Category::Turn => "turn",
+            Category::Defend => "defend",
+            Category::Attack => "attack",
+            Category::Neutral => "neutral",
+            Category::Inc => "inc",
+            Category::Bomb => "bomb",
+            Category::Search => "search",
+            Category::Timing => "timing",
+            Category::Logistics => "logistics",
+            Category::Opponent => "opponent",
+            Category::Sim => "sim"
+        }
+    }
+}
+
+// JSON lines trace, silent unless GITC_TRACE sets levels per category ("defend=debug,attack=info" or "all=info")
+// Lines go to stderr, or are appended to GITC_TRACE_FILE
+struct Tracer {
+    levels: [Level; 12],
+    output: Option<fs::File>,
+    turn: i32
+}
+
+impl Tracer {
+    pub fn load() -> Tracer {
+        let mut tracer = Tracer{levels: [Level::Off; 12], output: None, turn: 0};
+        let config = match env::var("GITC_TRACE") {
+            Ok(config) => config,
+            Err(_) => return tracer
+        };
+
+        for entry in config.split(',').map(|entry| entry.trim()).filter(|entry| !entry.is_empty()) {
+            let mut parts = entry.splitn(2, '=');
+            let name = parts.next().unwrap();
+            let level = match parts.next().unwrap_or("info") {
+                "off" => Level::Off,
+                "info" => Level::Info,
+                "debug" => Level::Debug,
+                level => { print_err!("Unknown trace level {}", level); continue }
+            };
+            for (i, category) in CATEGORIES.iter().enumerate() {
+                if name == "all" || name == category.name() {
+                    tracer.levels[i] = level;
+                }
+            }
+        }
+
+        if let Ok(path) = env::var("GITC_TRACE_FILE") {
+            match fs::OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => tracer.output = Some(file),
+                Err(err) => print_err!("Cannot open trace {}: {}", path, err)
+            }
+        }
+
+        return tracer;
+    }
+}
+
+thread_local! {
+    static TRACER: RefCell<Tracer> = RefCell::new(Tracer::load());
+}
+
+pub fn trace_enabled(category: Category, level: Level) -> bool {
+    return TRACER.with(|tracer| level <= tracer.borrow().levels[category as usize]);
+}
+
+pub fn trace_turn(turn: i32) {
+    TRACER.with(|tracer| tracer.borrow_mut().turn = turn);
+}
+
+pub fn trace_write(category: Category, level: Level, event: &str, fields: &[(&str, String)]) {
+    use std::io::Write;
+    TRACER.with(|tracer| {
+        let mut tracer = tracer.borrow_mut();
+        let mut line = format!("{{\"turn\":{},\"cat\":\"{}\",\"level\":\"{}\",\"event\":{}",
+                               tracer.turn, category.name(), if level == Level::Debug { "debug" } else { "info" }, event.to_json());
+        for &(key, ref value) in fields.iter() {
+            line.push_str(&format!(",{}:{}", key.to_json(), value));
+        }
+        line.push('}');
+
+        match tracer.output {
+            Some(ref mut file) => { writeln!(file, "{}", line).ok(); },
+            None => print_err!("{}", line)
+        }
+    });
+}
+
+pub trait TraceValue {
+    fn to_json(&self) -> String;
+}
+
+impl TraceValue for i32 {
+    fn to_json(&self) -> String { self.to_string() }
+}
+
+impl TraceValue for u64 {
+    fn to_json(&self) -> String { self.to_string() }
+}
+
+impl TraceValue for usize {
+    fn to_json(&self) -> String { self.to_string() }
+}
+
+impl TraceValue for f32 {
+    fn to_json(&self) -> String { if self.is_finite() { self.to_string() } else { "null".to_string() } }
+}
+
+impl TraceValue for bool {
+    fn to_json(&self) -> String { self.to_string() }
+}
+
+impl TraceValue for str {
+    fn to_json(&self) -> String {
+        let mut json = String::from("\"");
+        for c in self.chars() {
+            match c {
+                '"' => json.push_str("\\\""),
+                '\\' => json.push_str("\\\\"),
+                c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+                c => json.push(c)
+            }
+        }
+        json.push('"');
+        return json;
+    }
+}
+
+impl TraceValue for String {
+    fn to_json(&self) -> String { self.as_str().to_json() }
+}
+
+impl<T: TraceValue> TraceValue for Vec<T> {
+    fn to_json(&self) -> String {
+        let values: Vec<String> = self.iter().map(|value| value.to_json()).collect();
+        return format!("[{}]", values.join(","));
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use crate::model::{GameState, HasOwner};
+use crate::trace::{Category, Level};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Launch {
+    Ours,
+    Enemy, // Inferred from a new enemy troop
+    InFlight // Already on its way when the tracking started
+}
+
+// A troop followed by its entity id from its first turn to its arrival
+#[derive(Clone, PartialEq, Debug)]
+pub struct TrackedTroop {
+    pub owner: i32,
+    pub factory_start: i32,
+    pub factory_end: i32,
+    pub cyborg_count: i32,
+    pub launch_turn: i32, // Turn of the MOVE order
+    pub arrival_turn: i32, // First turn the troop is gone and its fight is in the input
+    pub launch: Launch
+}
+
+impl TrackedTroop {
+    pub fn travel_turns(&self) -> i32 {
+        return self.arrival_turn - self.launch_turn;
+    }
+}
+
+impl GameState {
+    // Match the troops of this turn with the tracked ones, a troop that does not move by one turn is a simulator mismatch
+    pub fn update_tracked_troops(&mut self) {
+        let started = self.last_seen.is_some();
+        let mut tracked = BTreeMap::new();
+        for troop in self.troops.iter() {
+            let distance = self.distance(troop.factory_start, troop.factory_end);
+            let record = match self.tracked_troops.remove(&troop.id) {
+                Some(record) => record,
+                None => TrackedTroop{owner: troop.owner, factory_start: troop.factory_start, factory_end: troop.factory_end, cyborg_count: troop.cyborg_count,
+                                     launch_turn: self.nb_turn - 1 - (distance - troop.turn_remaining), arrival_turn: self.nb_turn + troop.turn_remaining,
+                                     launch: if !started { Launch::InFlight } else if troop.is_player() { Launch::Ours } else { Launch::Enemy }}
+            };
+
+            if record.arrival_turn - self.nb_turn != troop.turn_remaining || record.cyborg_count != troop.cyborg_count {
+                trace!(Category::Sim, Level::Info, "troop_progress", "id" => troop.id, "expected_turns" => record.arrival_turn - self.nb_turn,
+                       "turns" => troop.turn_remaining, "expected_count" => record.cyborg_count, "count" => troop.cyborg_count);
+            }
+            tracked.insert(troop.id, record);
+        }
+
+        for (id, record) in self.tracked_troops.iter() {
+            if record.arrival_turn > self.nb_turn {
+                trace!(Category::Sim, Level::Info, "troop_progress", "id" => *id, "expected_turns" => record.arrival_turn - self.nb_turn, "turns" => 0);
+            }
+        }
+        self.tracked_troops = tracked;
+    }
+
+    // Cyborgs of `side` on their way to `id`
+    pub fn committed(&self, side: i32, id: i32) -> i32 {
+        return self.tracked_troops.values()
+            .filter(|record| record.owner == side && record.factory_end == id)
+            .map(|record| record.cyborg_count)
+            .sum();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Troop;
+    use crate::testing::{new_map, troop};
+
+    fn state() -> GameState {
+        let mut state = new_map(&[(0, 1, 20, 1), (1, -1, 20, 1)], &[(0, 1, 5)]);
+        state.nb_turn = 3;
+        state.troops.push_back(Troop{id: 4, ..troop(-1, 1, 0, 6, 4)});
+        state.update_tracked_troops();
+        state.update_profile();
+        return state;
+    }
+
+    #[test]
+    fn troops_keep_their_launch_turn() {
+        let mut state = state();
+        let first = state.tracked_troops.get(&4).unwrap().clone();
+        assert_eq!((first.launch_turn, first.arrival_turn, first.travel_turns(), first.launch), (1, 7, 6, Launch::InFlight));
+
+        state.nb_turn = 4;
+        state.troops.front_mut().unwrap().turn_remaining = 3;
+        state.troops.push_back(Troop{id: 5, ..troop(1, 0, 1, 8, 5)});
+        state.troops.push_back(Troop{id: 6, ..troop(-1, 1, 0, 3, 5)});
+        state.update_tracked_troops();
+
+        assert_eq!(state.tracked_troops.get(&4), Some(&first));
+        let launches: Vec<(Launch, i32, i32)> = [5, 6].iter().map(|id| state.tracked_troops.get(id).unwrap()).map(|record| (record.launch, record.launch_turn, record.arrival_turn)).collect();
+        assert_eq!(launches, vec![(Launch::Ours, 3, 9), (Launch::Enemy, 3, 9)]);
+        assert_eq!(state.committed(-1, 0), 9);
+    }
+
+    #[test]
+    fn arrived_troops_are_dropped() {
+        let mut state = state();
+        state.nb_turn = 7;
+        state.troops.clear();
+        state.update_tracked_troops();
+
+        assert!(state.tracked_troops.is_empty());
+    }
+}